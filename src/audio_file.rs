@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::time::Duration;
+
+/// audio decoded from a WAV or FLAC file, samples scaled to `-1.0..1.0`
+pub struct AudioFile {
+    pub sample_rate: u32,
+    pub channel_count: usize,
+    /// interleaved samples of all channels
+    pub samples: Vec<f32>,
+}
+
+/// scales a signed integer sample with `bits` bits to `-1.0..1.0`
+fn int_to_f32(sample: i32, bits: u32) -> f32 {
+    sample as f32 / (1u64 << (bits - 1)) as f32
+}
+
+impl AudioFile {
+    /// decodes a `.wav` or `.flac` file, chosen by the extension
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, &'static str> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("wav") | Some("wave") => Self::open_wav(path),
+            Some("flac") => Self::open_flac(path),
+            Some("ogg") | Some("oga") => {
+                Err("ogg files are not supported, convert them to wav or flac")
+            }
+            _ => Err("unsupported file format"),
+        }
+    }
+
+    pub fn open_wav<P: AsRef<Path>>(path: P) -> Result<Self, &'static str> {
+        let mut reader = hound::WavReader::open(path).map_err(|_| "cannot read wav file")?;
+        let spec = reader.spec();
+        let samples: Result<Vec<f32>, hound::Error> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect(),
+            hound::SampleFormat::Int => {
+                let bits = spec.bits_per_sample as u32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| int_to_f32(sample, bits)))
+                    .collect()
+            }
+        };
+        Self::new(
+            spec.sample_rate,
+            spec.channels as usize,
+            samples.map_err(|_| "invalid wav data")?,
+        )
+    }
+
+    pub fn open_flac<P: AsRef<Path>>(path: P) -> Result<Self, &'static str> {
+        let mut reader = claxon::FlacReader::open(path).map_err(|_| "cannot read flac file")?;
+        let streaminfo = reader.streaminfo();
+        let bits = streaminfo.bits_per_sample;
+        let samples: Result<Vec<f32>, claxon::Error> = reader
+            .samples()
+            .map(|sample| sample.map(|sample| int_to_f32(sample, bits)))
+            .collect();
+        Self::new(
+            streaminfo.sample_rate,
+            streaminfo.channels as usize,
+            samples.map_err(|_| "invalid flac data")?,
+        )
+    }
+
+    /// `samples` are interleaved, incomplete frames at the end are cut off
+    pub fn new(
+        sample_rate: u32,
+        channel_count: usize,
+        mut samples: Vec<f32>,
+    ) -> Result<Self, &'static str> {
+        if sample_rate == 0 || channel_count == 0 {
+            return Err("invalid format");
+        }
+        samples.truncate(samples.len() - samples.len() % channel_count);
+        Ok(Self {
+            sample_rate,
+            channel_count,
+            samples,
+        })
+    }
+
+    /// number of samples per channel
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channel_count
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frame_count() as f64 / self.sample_rate as f64)
+    }
+
+    /// samples of `channel`
+    /// # Panics
+    /// panics unless `channel < self.channel_count`
+    pub fn channel(&self, channel: usize) -> Vec<f32> {
+        assert!(channel < self.channel_count);
+        self.samples
+            .iter()
+            .skip(channel)
+            .step_by(self.channel_count)
+            .copied()
+            .collect()
+    }
+
+    /// mean of all channels
+    pub fn mono(&self) -> Vec<f32> {
+        self.samples
+            .chunks_exact(self.channel_count)
+            .map(|frame| frame.iter().sum::<f32>() / self.channel_count as f32)
+            .collect()
+    }
+}
@@ -0,0 +1,147 @@
+use crate::fifo_queue::{FifoConsumer, FifoProducer, OverflowPolicy};
+use crate::spsc_queue::{spsc_queue, SpscConsumer, SpscProducer};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+/// fan-out of frames, e.g. spectrum columns, to any number of subscribers
+/// like a display, a recorder, a network streamer and a feature extractor.
+///
+/// every subscriber reads from its own lock-free queue of shared frames,
+/// so it has its own cursor, `OverflowPolicy` and count of dropped frames.
+/// subscribers may come and go while frames are published,
+/// a subscriber with `OverflowPolicy::Block` holds up all others while it is full
+pub struct Broadcast<T> {
+    subscribers: Vec<SpscProducer<Arc<T>>>,
+    new_subscribers: Receiver<SpscProducer<Arc<T>>>,
+    subscriptions: Subscriptions<T>,
+}
+
+/// handle to subscribe to a `Broadcast` from another thread
+pub struct Subscriptions<T> {
+    sender: Sender<SpscProducer<Arc<T>>>,
+}
+
+// derived `Clone` would require `T: Clone`
+impl<T> Clone for Subscriptions<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+/// queue of the frames published after subscribing
+pub struct Subscriber<T> {
+    queue: SpscConsumer<Arc<T>>,
+}
+
+impl<T> Default for Broadcast<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Broadcast<T> {
+    pub fn new() -> Self {
+        let (sender, new_subscribers) = channel();
+        Self {
+            subscribers: Vec::new(),
+            new_subscribers,
+            subscriptions: Subscriptions { sender },
+        }
+    }
+
+    pub fn subscriptions(&self) -> Subscriptions<T> {
+        self.subscriptions.clone()
+    }
+
+    /// see `Subscriptions::subscribe`
+    pub fn subscribe(&self, size: usize, policy: OverflowPolicy) -> Subscriber<T> {
+        self.subscriptions.subscribe(size, policy)
+    }
+
+    /// takes new subscribers and forgets dropped ones
+    fn update_subscribers(&mut self) {
+        self.subscribers.extend(self.new_subscribers.try_iter());
+        self.subscribers
+            .retain(|subscriber| !subscriber.is_abandoned());
+    }
+
+    pub fn has_subscribers(&mut self) -> bool {
+        self.update_subscribers();
+        !self.subscribers.is_empty()
+    }
+
+    pub fn subscriber_count(&mut self) -> usize {
+        self.update_subscribers();
+        self.subscribers.len()
+    }
+
+    /// true while a full subscriber with `OverflowPolicy::Block` waits for its consumer,
+    /// see `SpscProducer::is_blocked`
+    pub fn is_blocked(&mut self) -> bool {
+        self.update_subscribers();
+        self.subscribers
+            .iter_mut()
+            .any(|subscriber| subscriber.is_blocked())
+    }
+
+    /// shares `frame` with all subscribers
+    pub fn publish(&mut self, frame: T) {
+        self.update_subscribers();
+        let frame = Arc::new(frame);
+        for subscriber in self.subscribers.iter_mut() {
+            subscriber.push(frame.clone());
+        }
+    }
+
+    /// publishes the frame made by `make_frame`,
+    /// which is only called if anyone subscribed
+    pub fn publish_with<F: FnOnce() -> T>(&mut self, make_frame: F) {
+        if self.has_subscribers() {
+            self.publish(make_frame());
+        }
+    }
+}
+
+impl<T> Subscriptions<T> {
+    /// a subscriber keeping up to `size` frames, see `spsc_queue`.
+    /// it receives frames once the `Broadcast` noticed it with the next `publish`
+    /// # Panics
+    /// panics if `size` is 0 or on `OverflowPolicy::Decimate(0)`
+    pub fn subscribe(&self, size: usize, policy: OverflowPolicy) -> Subscriber<T> {
+        let (mut producer, queue) = spsc_queue(size);
+        producer.set_policy(policy);
+        // a dropped broadcast never publishes, the subscriber just stays empty
+        let _ = self.sender.send(producer);
+        Subscriber { queue }
+    }
+}
+
+impl<T> Subscriber<T> {
+    pub fn pop(&mut self) -> Option<Arc<T>> {
+        self.queue.pop()
+    }
+
+    /// all frames published since the last call
+    pub fn drain(&mut self) -> Vec<Arc<T>> {
+        let mut frames = Vec::with_capacity(self.queue.len());
+        while let Some(frame) = self.queue.pop() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// number of frames this subscriber missed because its queue was full
+    pub fn dropped(&self) -> usize {
+        self.queue.dropped()
+    }
+}
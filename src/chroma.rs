@@ -0,0 +1,216 @@
+use crate::stft::FromF64;
+use num::complex::Complex;
+
+/// names of the 12 pitch classes, chroma bin 0 is C
+pub static PITCH_CLASS_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// name of the pitch class nearest to chroma `bin` of `bins_per_octave` bins
+pub fn pitch_class_name(bin: usize, bins_per_octave: usize) -> &'static str {
+    let semitone = (bin * 12 + bins_per_octave / 2) / bins_per_octave;
+    PITCH_CLASS_NAMES[semitone % 12]
+}
+
+/// chroma (pitch class profile) of linear magnitude columns of an `STFT`
+///
+/// the power of every stft bin between `min_frequency` and `max_frequency` is
+/// distributed onto the two nearest of `bins_per_octave` pitch classes, starting at C.
+/// `tuning` shifts the equal-tempered grid in semitones (`-0.5..0.5`),
+/// `estimated_tuning()` estimates it from the spectral peaks of all computed columns
+pub struct Chroma<T> {
+    pub sample_rate: f32,
+    pub fft_size: usize,
+    pub bins_per_octave: usize,
+    /// frequency of A4 in Hz
+    pub reference_frequency: f32,
+    pub min_frequency: f32,
+    pub max_frequency: f32,
+    /// deviation of the grid from `reference_frequency` in semitones
+    pub tuning: f32,
+    /// scale every column to a maximum of 1.0
+    pub normalize: bool,
+    /// per stft bin: lower chroma bin and weight of the upper neighbor
+    pub bin_map: Vec<Option<(usize, T)>>,
+    /// weighted circular mean of the peak deviations from the 12-TET grid
+    pub tuning_accumulator: Complex<f64>,
+}
+
+impl<T> Chroma<T>
+where
+    T: num::Float + FromF64,
+{
+    pub const DEFAULT_REFERENCE_FREQUENCY: f32 = 440.0;
+
+    /// * `sample_rate` - sample rate of the analyzed signal in Hz
+    /// * `fft_size` - fft size of the `STFT` the columns come from
+    /// * `bins_per_octave` - number of chroma bins, 12 for semitones
+    /// * `reference_frequency` - frequency of A4 in Hz
+    /// * `min_frequency`, `max_frequency` - range of stft bins used in Hz
+    pub fn new(
+        sample_rate: f32,
+        fft_size: usize,
+        bins_per_octave: usize,
+        reference_frequency: f32,
+        min_frequency: f32,
+        max_frequency: f32,
+    ) -> Self {
+        assert!(bins_per_octave > 0);
+        assert!(reference_frequency > 0.0);
+        assert!(min_frequency > 0.0);
+        assert!(min_frequency < max_frequency);
+        let mut chroma = Chroma {
+            sample_rate,
+            fft_size,
+            bins_per_octave,
+            reference_frequency,
+            min_frequency,
+            max_frequency,
+            tuning: 0.0,
+            normalize: true,
+            bin_map: Vec::new(),
+            tuning_accumulator: Complex::new(0.0, 0.0),
+        };
+        chroma.update_bin_map();
+        chroma
+    }
+
+    /// shifts the pitch class grid by `tuning` semitones
+    pub fn set_tuning(&mut self, tuning: f32) {
+        self.tuning = tuning;
+        self.update_bin_map();
+    }
+
+    /// frequency in Hz of C0 including the tuning offset
+    fn c0_frequency(&self) -> f64 {
+        // C0 is 57 semitones below A4
+        self.reference_frequency as f64 * 2f64.powf((self.tuning as f64 - 57.0) / 12.0)
+    }
+
+    fn update_bin_map(&mut self) {
+        let c0_frequency = self.c0_frequency();
+        let bin_width = self.sample_rate as f64 / self.fft_size as f64;
+        let bins_per_octave = self.bins_per_octave as f64;
+        self.bin_map = (0..self.fft_size / 2 + 1)
+            .map(|bin| {
+                let frequency = bin as f64 * bin_width;
+                if frequency < self.min_frequency as f64 || frequency > self.max_frequency as f64 {
+                    return None;
+                }
+                let position = (bins_per_octave * (frequency / c0_frequency).log2())
+                    .rem_euclid(bins_per_octave);
+                let lower = position.floor();
+                let chroma_bin = (lower as usize) % self.bins_per_octave;
+                Some((chroma_bin, FromF64::from_f64(position - lower)))
+            })
+            .collect();
+    }
+
+    #[inline]
+    pub fn output_size(&self) -> usize {
+        self.bins_per_octave
+    }
+
+    /// name of the pitch class nearest to `bin`
+    pub fn bin_name(&self, bin: usize) -> &'static str {
+        pitch_class_name(bin, self.bins_per_octave)
+    }
+
+    /// deviation of `magnitudes`' spectral peaks from the 12-TET grid in semitones,
+    /// `None` if there are no peaks in the frequency range
+    pub fn estimate_tuning(&self, magnitudes: &[T]) -> Option<f32> {
+        let sum = self.tuning_sum(magnitudes);
+        if sum.norm() > 0.0 {
+            Some((sum.arg() / (2.0 * std::f64::consts::PI)) as f32)
+        } else {
+            None
+        }
+    }
+
+    /// tuning estimated from all columns computed so far,
+    /// relative to `reference_frequency` not including `tuning`
+    pub fn estimated_tuning(&self) -> Option<f32> {
+        if self.tuning_accumulator.norm() > 0.0 {
+            Some((self.tuning_accumulator.arg() / (2.0 * std::f64::consts::PI)) as f32)
+        } else {
+            None
+        }
+    }
+
+    /// uses the estimated tuning for the following columns
+    pub fn apply_estimated_tuning(&mut self) {
+        if let Some(tuning) = self.estimated_tuning() {
+            self.set_tuning(tuning);
+        }
+    }
+
+    /// magnitude weighted sum of unit phasors of the peak deviations,
+    /// peaks are refined by parabolic interpolation of the log magnitudes
+    fn tuning_sum(&self, magnitudes: &[T]) -> Complex<f64> {
+        let bin_width = self.sample_rate as f64 / self.fft_size as f64;
+        let a4_frequency = self.reference_frequency as f64;
+        let mut sum = Complex::new(0.0, 0.0);
+        // bins beyond the bin map, e.g. of longer columns, are ignored like in `compute`
+        for ((bin, neighbors), mapping) in (1..)
+            .zip(magnitudes.windows(3))
+            .zip(self.bin_map.iter().skip(1))
+        {
+            let (left, center, right) = (neighbors[0], neighbors[1], neighbors[2]);
+            if center <= left || center < right || mapping.is_none() {
+                continue;
+            }
+            let (Some(left), Some(center), Some(right)) =
+                (left.to_f64(), center.to_f64(), right.to_f64())
+            else {
+                continue;
+            };
+            let (log_left, log_center, log_right) = (
+                left.max(1e-20).ln(),
+                center.max(1e-20).ln(),
+                right.max(1e-20).ln(),
+            );
+            let denominator = log_left - 2.0 * log_center + log_right;
+            let offset = if denominator.abs() > 1e-12 {
+                (0.5 * (log_left - log_right) / denominator).clamp(-0.5, 0.5)
+            } else {
+                0.0
+            };
+            let frequency = (bin as f64 + offset) * bin_width;
+            let semitones = 12.0 * (frequency / a4_frequency).log2();
+            let deviation = semitones - semitones.round();
+            sum += Complex::from_polar(center, 2.0 * std::f64::consts::PI * deviation);
+        }
+        sum
+    }
+
+    /// computes a chroma column of linear `magnitudes`
+    /// and accumulates their tuning estimate
+    /// # Panics
+    /// panics unless `self.output_size() == output.len()`
+    pub fn compute(&mut self, magnitudes: &[T], output: &mut [T]) {
+        assert_eq!(self.output_size(), output.len());
+
+        let tuning_sum = self.tuning_sum(magnitudes);
+        self.tuning_accumulator += tuning_sum;
+
+        for dst in output.iter_mut() {
+            *dst = T::zero();
+        }
+        for (magnitude, mapping) in magnitudes.iter().zip(self.bin_map.iter()) {
+            if let Some((chroma_bin, upper_weight)) = *mapping {
+                let power = *magnitude * *magnitude;
+                let upper_bin = (chroma_bin + 1) % self.bins_per_octave;
+                output[chroma_bin] = output[chroma_bin] + power * (T::one() - upper_weight);
+                output[upper_bin] = output[upper_bin] + power * upper_weight;
+            }
+        }
+        if self.normalize {
+            let max = output.iter().fold(T::zero(), |acc, &value| acc.max(value));
+            if max > T::zero() {
+                for dst in output.iter_mut() {
+                    *dst = *dst / max;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::str::FromStr;
+
+/// colors of spectrogram pixels, shared by the gui and the png renderer
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Colormap {
+    /// black to white, the gui's spectrogram
+    #[default]
+    Gray,
+    /// black over red and yellow to white
+    Heat,
+    /// perceptually uniform dark blue over green to yellow
+    Viridis,
+}
+
+/// coefficients of a polynomial fit of matplotlib's viridis, constant term first
+const VIRIDIS: [[f32; 3]; 7] = [
+    [0.277_727_3, 0.005_407_344, 0.334_099_8],
+    [0.105_093_04, 1.404_613_5, 1.384_590_2],
+    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+    [-4.634_230_5, -5.799_101, -19.332_441],
+    [6.228_27, 14.179_933, 56.690_55],
+    [4.776_385, -13.745_145, -65.353_03],
+    [-5.435_456, 4.645_852_6, 26.312_435],
+];
+
+/// display value in `0..=1` quantized to the gray level of a pixel
+#[inline]
+pub fn level(value: f32) -> u8 {
+    (255.0 * value) as u8
+}
+
+impl Colormap {
+    pub fn values() -> [Colormap; 3] {
+        static COLORMAPS: [Colormap; 3] = [Colormap::Gray, Colormap::Heat, Colormap::Viridis];
+        COLORMAPS
+    }
+
+    /// rgb color of a gray `level`
+    pub fn rgb(&self, level: u8) -> [u8; 3] {
+        let t = level as f32 / 255.0;
+        let channels = match self {
+            Colormap::Gray => return [level; 3],
+            Colormap::Heat => [3.0 * t, 3.0 * t - 1.0, 3.0 * t - 2.0],
+            Colormap::Viridis => {
+                let mut channels = [0.0; 3];
+                for (channel, value) in channels.iter_mut().enumerate() {
+                    *value = VIRIDIS
+                        .iter()
+                        .rev()
+                        .fold(0.0, |sum, coefficients| sum * t + coefficients[channel]);
+                }
+                channels
+            }
+        };
+        channels.map(|channel| (255.0 * channel.clamp(0.0, 1.0)).round() as u8)
+    }
+
+    /// rgb color of a display value in `0..=1`
+    pub fn value_rgb(&self, value: f32) -> [u8; 3] {
+        self.rgb(level(value))
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        match &lower[..] {
+            "gray" => Ok(Colormap::Gray),
+            "grey" => Ok(Colormap::Gray),
+            "heat" => Ok(Colormap::Heat),
+            "viridis" => Ok(Colormap::Viridis),
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for Colormap {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
@@ -0,0 +1,273 @@
+use crate::stft::{FromF64, ScalingMode};
+use num::complex::Complex;
+use num::traits::Signed;
+use realfft::{FftNum, RealFftPlanner, RealToComplex};
+use std::sync::Arc;
+
+/// spectral kernel values below this fraction of the kernel's maximum are dropped
+const KERNEL_THRESHOLD: f64 = 0.005;
+
+/// sparse spectral kernel of one constant-Q bin
+pub struct SpectralKernel<T> {
+    /// first fft bin of `values`
+    pub start: usize,
+    pub values: Vec<Complex<T>>,
+}
+
+/// constant-Q transform, using the spectral kernel method of Brown and Puckette
+///
+/// bins are spaced logarithmically, `bins_per_octave` per octave starting at `min_frequency`,
+/// all bins have the same quality factor `Q = 1 / (2^(1 / bins_per_octave) - 1)`.
+/// the window of the lowest bin defines the number of input samples (`window_size()`),
+/// the windows of all bins are centered in it
+pub struct CQT<T>
+where
+    T: FftNum + FromF64 + num::Float,
+{
+    pub sample_rate: f32,
+    pub min_frequency: f32,
+    pub bins_per_octave: usize,
+    pub bin_count: usize,
+    pub fft_size: usize,
+    pub step_size: usize,
+    pub rfft: Arc<dyn RealToComplex<T>>,
+    pub real_input: Vec<T>,
+    pub scratch_space: Vec<Complex<T>>,
+    pub spectrum: Vec<Complex<T>>,
+    pub kernels: Vec<SpectralKernel<T>>,
+    /// complex column reused by `compute_column`
+    pub complex_output: Vec<Complex<T>>,
+    pub scaling: ScalingMode,
+}
+
+impl<T> CQT<T>
+where
+    T: FftNum + FromF64 + num::Float + Signed,
+{
+    /// * `sample_rate` - sample rate of the input in Hz
+    /// * `min_frequency` - center frequency of the first bin in Hz
+    /// * `bins_per_octave` - resolution, 12 for semitones
+    /// * `bin_count` - number of bins, highest bin has to be below nyquist
+    /// * `step_size` - number of samples between two columns
+    pub fn new(
+        sample_rate: f32,
+        min_frequency: f32,
+        bins_per_octave: usize,
+        bin_count: usize,
+        step_size: usize,
+    ) -> Self {
+        assert!(bins_per_octave > 0);
+        assert!(bin_count > 0);
+        assert!(step_size > 0);
+        assert!(min_frequency > 0.0);
+        let max_frequency = Self::frequency(min_frequency, bins_per_octave, bin_count - 1);
+        assert!(max_frequency < sample_rate / 2.0);
+
+        let quality = Self::quality(bins_per_octave);
+        let max_window_size = (quality * sample_rate as f64 / min_frequency as f64).ceil() as usize;
+        let fft_size = max_window_size.next_power_of_two();
+
+        let mut real_planner = RealFftPlanner::<T>::new();
+        let rfft = real_planner.plan_fft_forward(fft_size);
+        let scratch_space = rfft.make_scratch_vec();
+        let real_input = rfft.make_input_vec();
+        let spectrum = rfft.make_output_vec();
+
+        let kernels = (0..bin_count)
+            .map(|bin| {
+                let frequency = Self::frequency(min_frequency, bins_per_octave, bin);
+                Self::make_kernel(&rfft, sample_rate, frequency, quality, fft_size)
+            })
+            .collect();
+
+        CQT {
+            sample_rate,
+            min_frequency,
+            bins_per_octave,
+            bin_count,
+            fft_size,
+            step_size,
+            rfft,
+            real_input,
+            scratch_space,
+            spectrum,
+            kernels,
+            complex_output: vec![Complex::new(T::zero(), T::zero()); bin_count],
+            scaling: ScalingMode::default(),
+        }
+    }
+
+    /// quality factor: center frequency / bandwidth
+    pub fn quality(bins_per_octave: usize) -> f64 {
+        1.0 / (2.0f64.powf(1.0 / bins_per_octave as f64) - 1.0)
+    }
+
+    fn frequency(min_frequency: f32, bins_per_octave: usize, bin: usize) -> f32 {
+        min_frequency * 2.0f32.powf(bin as f32 / bins_per_octave as f32)
+    }
+
+    /// spectrum of the hanning windowed complex exponential of `frequency`,
+    /// scaled so a sine with amplitude 1.0 gives a magnitude of 1.0
+    fn make_kernel(
+        rfft: &Arc<dyn RealToComplex<T>>,
+        sample_rate: f32,
+        frequency: f32,
+        quality: f64,
+        fft_size: usize,
+    ) -> SpectralKernel<T> {
+        let window_size =
+            ((quality * sample_rate as f64 / frequency as f64).ceil() as usize).clamp(2, fft_size);
+        let window: Vec<f64> = apodize::hanning_iter(window_size).collect();
+        let window_sum: f64 = window.iter().sum();
+        let offset = (fft_size - window_size) / 2;
+
+        // realfft only transforms real input, so the real and imaginary part
+        // of the temporal kernel are transformed separately
+        let mut real_part = rfft.make_input_vec();
+        let mut imaginary_part = rfft.make_input_vec();
+        for (index, window_elem) in window.iter().enumerate() {
+            let phase =
+                2.0 * std::f64::consts::PI * frequency as f64 * index as f64 / sample_rate as f64;
+            let amplitude = 2.0 * window_elem / window_sum;
+            real_part[offset + index] = FromF64::from_f64(amplitude * phase.cos());
+            imaginary_part[offset + index] = FromF64::from_f64(amplitude * phase.sin());
+        }
+        let mut real_spectrum = rfft.make_output_vec();
+        let mut imaginary_spectrum = rfft.make_output_vec();
+        let _ = rfft.process(&mut real_part, &mut real_spectrum);
+        let _ = rfft.process(&mut imaginary_part, &mut imaginary_spectrum);
+
+        // positive frequencies of the complex kernel, conjugated and scaled by 1 / N
+        let scale: T = FromF64::from_f64(1.0 / fft_size as f64);
+        let spectrum: Vec<Complex<T>> = real_spectrum
+            .iter()
+            .zip(imaginary_spectrum.iter())
+            .map(|(re, im)| (re + Complex::new(-im.im, im.re)).conj() * scale)
+            .collect();
+
+        let max = spectrum
+            .iter()
+            .fold(T::zero(), |acc, elem| acc.max(elem.norm()));
+        let threshold = max * FromF64::from_f64(KERNEL_THRESHOLD);
+        let start = spectrum
+            .iter()
+            .position(|elem| elem.norm() >= threshold)
+            .unwrap_or(0);
+        let end = spectrum
+            .iter()
+            .rposition(|elem| elem.norm() >= threshold)
+            .map_or(start, |end| end + 1);
+        SpectralKernel {
+            start,
+            values: spectrum[start..end].to_vec(),
+        }
+    }
+
+    /// number of input samples per column
+    #[inline]
+    pub fn window_size(&self) -> usize {
+        self.fft_size
+    }
+
+    #[inline]
+    pub fn output_size(&self) -> usize {
+        self.bin_count
+    }
+
+    /// center frequency in Hz of `bin`
+    #[inline]
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        Self::frequency(self.min_frequency, self.bins_per_octave, bin)
+    }
+
+    /// nearest bin of `frequency` in Hz, clamped to the existing bins
+    #[inline]
+    pub fn frequency_bin(&self, frequency: f32) -> usize {
+        let bin = (self.bins_per_octave as f32 * (frequency / self.min_frequency).log2()).round();
+        (bin.max(0.0) as usize).min(self.bin_count - 1)
+    }
+
+    /// sets the scaling applied by `compute_column`
+    pub fn set_scaling(&mut self, scaling: ScalingMode) {
+        self.scaling = scaling;
+    }
+
+    /// computes the complex constant-Q coefficients of `input`
+    /// # Panics
+    /// panics unless `self.window_size() == input.len()`
+    /// and `self.output_size() == output.len()`
+    pub fn compute_into_complex_output(&mut self, input: &[T], output: &mut [Complex<T>]) {
+        assert_eq!(self.window_size(), input.len());
+        assert_eq!(self.output_size(), output.len());
+
+        self.real_input.copy_from_slice(input);
+        let _ = self.rfft.process_with_scratch(
+            &mut self.real_input,
+            &mut self.spectrum,
+            &mut self.scratch_space,
+        );
+        for (dst, kernel) in output.iter_mut().zip(self.kernels.iter()) {
+            *dst = self.spectrum[kernel.start..]
+                .iter()
+                .zip(kernel.values.iter())
+                .fold(Complex::new(T::zero(), T::zero()), |acc, (x, k)| {
+                    acc + x * k
+                });
+        }
+    }
+
+    /// computes a column of constant-Q magnitudes,
+    /// scaled according to `self.scaling`
+    /// # Panics
+    /// panics unless `self.window_size() == input.len()`
+    /// and `self.output_size() == output.len()`
+    pub fn compute_column(&mut self, input: &[T], output: &mut [T]) {
+        let mut complex_output = std::mem::take(&mut self.complex_output);
+        self.compute_into_complex_output(input, &mut complex_output);
+        self.complex_to_column(&complex_output, output);
+        self.complex_output = complex_output;
+    }
+
+    /// turns a complex column of `compute_into_complex_output` into
+    /// a magnitude column like `compute_column` does
+    /// # Panics
+    /// panics unless `self.output_size() == output.len()`
+    pub fn complex_to_column(&self, complex_column: &[Complex<T>], output: &mut [T]) {
+        assert_eq!(self.output_size(), output.len());
+
+        for (dst, src) in output.iter_mut().zip(complex_column.iter()) {
+            *dst = self.scaling.apply(src.norm());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_peaks_at_its_bin_with_its_amplitude() {
+        let sample_rate = 48000.0;
+        let mut cqt = CQT::<f32>::new(sample_rate, 55.0, 12, 60, 512);
+        cqt.set_scaling(ScalingMode::Linear);
+        let frequency = cqt.bin_frequency(33) as f64;
+        let amplitude = 0.5;
+        let input: Vec<f32> = (0..cqt.window_size())
+            .map(|index| {
+                let time = index as f64 / sample_rate as f64;
+                (amplitude * (2.0 * std::f64::consts::PI * frequency * time).sin()) as f32
+            })
+            .collect();
+        let mut column = vec![0.0; cqt.output_size()];
+        cqt.compute_column(&input, &mut column);
+        let peak = (0..column.len())
+            .max_by(|&a, &b| column[a].total_cmp(&column[b]))
+            .unwrap();
+        assert_eq!(peak, 33);
+        assert!(
+            (column[peak] - amplitude as f32).abs() < 0.01,
+            "magnitude {}",
+            column[peak]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::stft::FromF64;
+use num::complex::Complex;
+use std::str::FromStr;
+
+/// how `CrossSpectrum` averages successive columns
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default)]
+pub enum Averaging {
+    /// mean of all columns since the last reset
+    #[default]
+    Linear,
+    /// exponential moving average, every new column is weighted with `alpha` in `0..=1`
+    Exponential(f64),
+}
+
+impl Averaging {
+    pub const DEFAULT_ALPHA: f64 = 0.1;
+
+    pub fn values() -> [Averaging; 2] {
+        static AVERAGING_MODES: [Averaging; 2] = [
+            Averaging::Linear,
+            Averaging::Exponential(Averaging::DEFAULT_ALPHA),
+        ];
+        AVERAGING_MODES
+    }
+}
+
+impl FromStr for Averaging {
+    type Err = &'static str;
+
+    /// `"linear"`, `"exponential"` or `"exponential:<alpha>"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        let mut parts = lower.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let alpha = match parts.next() {
+            Some(alpha) => Some(alpha.parse::<f64>().map_err(|_| "invalid parameter")?),
+            None => None,
+        };
+        match name {
+            "linear" if alpha.is_none() => Ok(Averaging::Linear),
+            "exponential" | "exp" => match alpha.unwrap_or(Averaging::DEFAULT_ALPHA) {
+                alpha if alpha > 0.0 && alpha <= 1.0 => Ok(Averaging::Exponential(alpha)),
+                _ => Err("invalid parameter"),
+            },
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for Averaging {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Averaging::Linear => write!(formatter, "Linear"),
+            Averaging::Exponential(alpha) => write!(formatter, "Exponential:{}", alpha),
+        }
+    }
+}
+
+/// averaged auto and cross spectra of a reference and a measurement signal,
+/// e.g. input and output of a system, from complex stft columns
+///
+/// * `reference_power` - `Gxx = avg(|X|²)`
+/// * `measurement_power` - `Gyy = avg(|Y|²)`
+/// * `cross_spectrum` - `Gxy = avg(conj(X) * Y)`
+///
+/// the transfer function is estimated as `H1 = Gxy / Gxx`,
+/// the magnitude-squared coherence is `|Gxy|² / (Gxx * Gyy)`
+pub struct CrossSpectrum<T> {
+    pub averaging: Averaging,
+    pub reference_power: Vec<T>,
+    pub measurement_power: Vec<T>,
+    pub cross_spectrum: Vec<Complex<T>>,
+    /// number of columns averaged since the last reset
+    pub count: usize,
+}
+
+impl<T> CrossSpectrum<T>
+where
+    T: num::Float + FromF64,
+{
+    pub fn new(bin_count: usize, averaging: Averaging) -> Self {
+        if let Averaging::Exponential(alpha) = averaging {
+            assert!(alpha > 0.0 && alpha <= 1.0);
+        }
+        CrossSpectrum {
+            averaging,
+            reference_power: vec![T::zero(); bin_count],
+            measurement_power: vec![T::zero(); bin_count],
+            cross_spectrum: vec![Complex::new(T::zero(), T::zero()); bin_count],
+            count: 0,
+        }
+    }
+
+    #[inline]
+    pub fn bin_count(&self) -> usize {
+        self.cross_spectrum.len()
+    }
+
+    /// restarts the averaging
+    pub fn reset(&mut self) {
+        for value in self.reference_power.iter_mut() {
+            *value = T::zero();
+        }
+        for value in self.measurement_power.iter_mut() {
+            *value = T::zero();
+        }
+        for value in self.cross_spectrum.iter_mut() {
+            *value = Complex::new(T::zero(), T::zero());
+        }
+        self.count = 0;
+    }
+
+    /// restarts the averaging with a different `averaging`
+    pub fn set_averaging(&mut self, averaging: Averaging) {
+        if let Averaging::Exponential(alpha) = averaging {
+            assert!(alpha > 0.0 && alpha <= 1.0);
+        }
+        self.averaging = averaging;
+        self.reset();
+    }
+
+    /// adds a pair of complex columns computed at the same time
+    /// # Panics
+    /// panics unless `reference` and `measurement` have `self.bin_count()` bins
+    pub fn add(&mut self, reference: &[Complex<T>], measurement: &[Complex<T>]) {
+        assert_eq!(self.bin_count(), reference.len());
+        assert_eq!(self.bin_count(), measurement.len());
+
+        self.count += 1;
+        // weight of the new column, the first column is taken as is
+        let weight: T = match self.averaging {
+            Averaging::Linear => FromF64::from_f64(1.0 / self.count as f64),
+            Averaging::Exponential(alpha) if self.count > 1 => FromF64::from_f64(alpha),
+            Averaging::Exponential(_) => T::one(),
+        };
+        for (bin, (x, y)) in reference.iter().zip(measurement.iter()).enumerate() {
+            let reference_power = x.norm_sqr();
+            let measurement_power = y.norm_sqr();
+            let cross = x.conj() * y;
+            self.reference_power[bin] =
+                self.reference_power[bin] + (reference_power - self.reference_power[bin]) * weight;
+            self.measurement_power[bin] = self.measurement_power[bin]
+                + (measurement_power - self.measurement_power[bin]) * weight;
+            self.cross_spectrum[bin] =
+                self.cross_spectrum[bin] + (cross - self.cross_spectrum[bin]).scale(weight);
+        }
+    }
+
+    /// transfer function `H1 = Gxy / Gxx`,
+    /// linear magnitude and phase in radians, zero where the reference is silent
+    /// # Panics
+    /// panics unless `magnitude` and `phase` have `self.bin_count()` bins
+    pub fn transfer_function(&self, magnitude: &mut [T], phase: &mut [T]) {
+        assert_eq!(self.bin_count(), magnitude.len());
+        assert_eq!(self.bin_count(), phase.len());
+
+        for ((magnitude, phase), (cross, reference_power)) in magnitude
+            .iter_mut()
+            .zip(phase.iter_mut())
+            .zip(self.cross_spectrum.iter().zip(self.reference_power.iter()))
+        {
+            if *reference_power > T::zero() {
+                let transfer = cross.unscale(*reference_power);
+                *magnitude = transfer.norm();
+                *phase = transfer.arg();
+            } else {
+                *magnitude = T::zero();
+                *phase = T::zero();
+            }
+        }
+    }
+
+    /// magnitude-squared coherence in `0..=1`, zero where a signal is silent.
+    /// a single column always has a coherence of 1
+    /// # Panics
+    /// panics unless `self.bin_count() == output.len()`
+    pub fn coherence(&self, output: &mut [T]) {
+        assert_eq!(self.bin_count(), output.len());
+
+        for (dst, ((cross, reference_power), measurement_power)) in output.iter_mut().zip(
+            self.cross_spectrum
+                .iter()
+                .zip(self.reference_power.iter())
+                .zip(self.measurement_power.iter()),
+        ) {
+            let denominator = *reference_power * *measurement_power;
+            *dst = if denominator > T::zero() {
+                (cross.norm_sqr() / denominator).min(T::one())
+            } else {
+                T::zero()
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stft::{WindowType, STFT};
+
+    /// deterministic white noise in `-1..1` (xorshift)
+    fn noise(seed: u64, length: usize) -> Vec<f32> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    /// averaged coherence of the stft columns of `reference` and `measurement`
+    fn coherence(reference: &[f32], measurement: &[f32]) -> Vec<f32> {
+        let (window_size, step_size) = (512, 256);
+        let mut stft = STFT::<f32>::new(WindowType::Hanning, window_size, step_size);
+        let bin_count = stft.complex_output_size();
+        let mut cross_spectrum = CrossSpectrum::new(bin_count, Averaging::Linear);
+        let mut reference_column = vec![Complex::new(0.0, 0.0); bin_count];
+        let mut measurement_column = vec![Complex::new(0.0, 0.0); bin_count];
+        for (reference, measurement) in reference
+            .windows(window_size)
+            .zip(measurement.windows(window_size))
+            .step_by(step_size)
+        {
+            stft.compute_into_complex_output(reference, &mut reference_column);
+            stft.compute_into_complex_output(measurement, &mut measurement_column);
+            cross_spectrum.add(&reference_column, &measurement_column);
+        }
+        let mut output = vec![0.0; bin_count];
+        cross_spectrum.coherence(&mut output);
+        output
+    }
+
+    #[test]
+    fn coherence_of_identical_inputs_is_one() {
+        let signal = noise(1, 256 * 64);
+        for (bin, value) in coherence(&signal, &signal).iter().enumerate() {
+            assert!((value - 1.0).abs() < 1e-4, "bin {bin}: {value}");
+        }
+    }
+
+    #[test]
+    fn coherence_of_independent_noise_is_near_zero() {
+        // about 400 averaged columns, the expected coherence is 1 / 400
+        let length = 256 * 400;
+        let output = coherence(&noise(1, length), &noise(2, length));
+        let mean = output.iter().sum::<f32>() / output.len() as f32;
+        let max = output.iter().fold(0.0f32, |max, &value| max.max(value));
+        assert!(mean < 0.01 && max < 0.05, "mean {mean}, max {max}");
+    }
+}
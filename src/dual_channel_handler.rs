@@ -0,0 +1,170 @@
+use crate::cross_spectrum::{Averaging, CrossSpectrum};
+use crate::stft::WindowType;
+use crate::stft::STFT;
+use crate::stft_handler::{copy_window, ConsumerRbf32};
+use num::complex::Complex;
+
+/// number of output columns kept by `DualChannelHandler::recycle_columns`,
+/// a transfer function and a coherence
+const RECYCLED_COLUMNS: usize = 3;
+
+/// dual-fft analyzer: stfts of a reference and a measurement ringbuffer,
+/// averaged into a `CrossSpectrum` column by column
+///
+/// both ringbuffers have to be fed synchronously,
+/// e.g. by the same jack process callback or a `StereoHandler`
+pub struct DualChannelHandler {
+    reference_out: ConsumerRbf32,
+    measurement_out: ConsumerRbf32,
+    /// stft of both channels, it keeps no state between columns
+    stft: STFT<f32>,
+    reference_samples: Vec<f32>,
+    measurement_samples: Vec<f32>,
+    reference_complex: Vec<Complex<f32>>,
+    measurement_complex: Vec<Complex<f32>>,
+    cross_spectrum: CrossSpectrum<f32>,
+    /// output columns handed back by `recycle_columns`
+    recycled_columns: Vec<Vec<f32>>,
+    sample_rate: f32,
+}
+
+impl DualChannelHandler {
+    pub fn new(reference_out: ConsumerRbf32, measurement_out: ConsumerRbf32) -> Self {
+        let mut dual_channel_handler = Self {
+            reference_out,
+            measurement_out,
+            stft: STFT::new_with_fft_size(WindowType::Hanning, 1024, 1024, 512),
+            reference_samples: Vec::new(),
+            measurement_samples: Vec::new(),
+            reference_complex: Vec::new(),
+            measurement_complex: Vec::new(),
+            cross_spectrum: CrossSpectrum::new(0, Averaging::default()),
+            recycled_columns: Vec::new(),
+            sample_rate: 48000.0,
+        };
+        dual_channel_handler.resize_buffers();
+        dual_channel_handler
+    }
+
+    /// sizes the buffers after the stft and restarts the averaging
+    fn resize_buffers(&mut self) {
+        let window_size = self.stft.window_size;
+        let complex_output_size = self.stft.complex_output_size();
+        self.reference_samples = vec![0.0; window_size];
+        self.measurement_samples = vec![0.0; window_size];
+        self.reference_complex = vec![Complex::new(0.0, 0.0); complex_output_size];
+        self.measurement_complex = vec![Complex::new(0.0, 0.0); complex_output_size];
+        self.cross_spectrum =
+            CrossSpectrum::new(complex_output_size, self.cross_spectrum.averaging);
+    }
+
+    /// replaces the stft of both channels and restarts the averaging
+    pub fn set_stft(
+        &mut self,
+        window_type: WindowType,
+        window_size: usize,
+        fft_size: usize,
+        step_size: usize,
+    ) {
+        self.stft = STFT::new_with_fft_size(window_type, window_size, fft_size, step_size);
+        self.resize_buffers();
+    }
+
+    pub fn run(&mut self) {
+        let window_size = self.stft.window_size;
+        let step_size = self.stft.step_size;
+        while self.reference_out.len() >= window_size && self.measurement_out.len() >= window_size {
+            copy_window(&self.reference_out, &mut self.reference_samples);
+            copy_window(&self.measurement_out, &mut self.measurement_samples);
+            self.reference_out.skip(step_size);
+            self.measurement_out.skip(step_size);
+
+            self.stft
+                .compute_into_complex_output(&self.reference_samples, &mut self.reference_complex);
+            self.stft.compute_into_complex_output(
+                &self.measurement_samples,
+                &mut self.measurement_complex,
+            );
+            self.cross_spectrum
+                .add(&self.reference_complex, &self.measurement_complex);
+        }
+    }
+
+    pub fn averaging(&self) -> Averaging {
+        self.cross_spectrum.averaging
+    }
+
+    /// restarts the averaging with a different `averaging`
+    pub fn set_averaging(&mut self, averaging: Averaging) {
+        self.cross_spectrum.set_averaging(averaging);
+    }
+
+    /// restarts the averaging
+    pub fn reset(&mut self) {
+        self.cross_spectrum.reset();
+    }
+
+    pub fn cross_spectrum(&self) -> &CrossSpectrum<f32> {
+        &self.cross_spectrum
+    }
+
+    /// number of columns averaged since the last reset
+    pub fn count(&self) -> usize {
+        self.cross_spectrum.count
+    }
+
+    /// number of bins of all outputs, `fft_size / 2 + 1`
+    pub fn output_size(&self) -> usize {
+        self.cross_spectrum.bin_count()
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// sample rate of the audio in the ringbuffers, used for bin to frequency mapping
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// center frequency in Hz of `bin`
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        self.stft.bin_frequency(bin, self.sample_rate)
+    }
+
+    /// a column of `output_size` values from the recycled columns,
+    /// allocated only if none is left
+    fn recycled_column(&mut self) -> Vec<f32> {
+        let mut column = self.recycled_columns.pop().unwrap_or_default();
+        column.clear();
+        column.resize(self.output_size(), 0.0);
+        column
+    }
+
+    /// linear magnitude and phase in radians of the transfer function
+    /// from reference to measurement
+    pub fn get_transfer_function(&mut self) -> (Vec<f32>, Vec<f32>) {
+        let mut magnitude = self.recycled_column();
+        let mut phase = self.recycled_column();
+        self.cross_spectrum
+            .transfer_function(&mut magnitude, &mut phase);
+        (magnitude, phase)
+    }
+
+    /// magnitude-squared coherence in `0..=1`
+    pub fn get_coherence(&mut self) -> Vec<f32> {
+        let mut coherence = self.recycled_column();
+        self.cross_spectrum.coherence(&mut coherence);
+        coherence
+    }
+
+    /// hands columns of `get_transfer_function` and `get_coherence` back
+    /// to be reused by the next calls
+    pub fn recycle_columns(&mut self, columns: Vec<Vec<f32>>) {
+        for column in columns {
+            if self.recycled_columns.len() < RECYCLED_COLUMNS {
+                self.recycled_columns.push(column);
+            }
+        }
+    }
+}
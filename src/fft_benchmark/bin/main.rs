@@ -0,0 +1,57 @@
+use num::complex::Complex;
+use spectrogram_lib::stft::{FftMode, WindowType, STFT};
+use std::time::{Duration, Instant};
+
+/// fft sizes compared, windows are as long as the fft
+const FFT_SIZES: [usize; 6] = [256, 512, 1024, 2048, 4096, 8192];
+/// number of columns per measurement if not given as first argument
+const DEFAULT_ITERATIONS: usize = 10000;
+
+/// mean time per column of `STFT::compute_into_complex_output` with `fft_mode`
+fn measure(fft_mode: FftMode, fft_size: usize, iterations: usize) -> Duration {
+    let mut stft = STFT::<f32>::new(WindowType::Hanning, fft_size, fft_size / 2);
+    stft.set_fft_mode(fft_mode);
+    let input: Vec<f32> = (0..fft_size)
+        .map(|index| (index as f32 * 0.1).sin())
+        .collect();
+    let mut output = vec![Complex::new(0.0, 0.0); stft.complex_output_size()];
+    // warm up caches and the fft plans
+    for _ in 0..iterations / 10 + 1 {
+        stft.compute_into_complex_output(&input, &mut output);
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        stft.compute_into_complex_output(&input, &mut output);
+    }
+    let elapsed = start.elapsed();
+    // keep the result alive
+    assert!(output.iter().all(|bin| bin.norm().is_finite()));
+    elapsed / iterations as u32
+}
+
+fn main() {
+    let iterations = match std::env::args().nth(1) {
+        Some(argument) => argument
+            .parse::<usize>()
+            .ok()
+            .filter(|&iterations| iterations > 0)
+            .expect("usage: fft_benchmark [iterations]"),
+        None => DEFAULT_ITERATIONS,
+    };
+    println!("time per stft column, {} columns each", iterations);
+    println!(
+        "{:>8} {:>12} {:>12} {:>8}",
+        "fft size", "RealFFT", "RustFFT", "ratio"
+    );
+    for fft_size in FFT_SIZES {
+        let real_fft = measure(FftMode::RealFFT, fft_size, iterations);
+        let rust_fft = measure(FftMode::RustFFT, fft_size, iterations);
+        println!(
+            "{:>8} {:>12?} {:>12?} {:>8.2}",
+            fft_size,
+            real_fft,
+            rust_fft,
+            rust_fft.as_secs_f64() / real_fft.as_secs_f64()
+        );
+    }
+}
@@ -1,7 +1,100 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+/// queue of frames, e.g. spectrum columns, popped in the order they were pushed.
+/// object safe, backends are created by their own constructors
 pub trait FifoQueue<T> {
-    fn new(size: usize) -> Self;
     fn push(&mut self, new_data: T);
     fn pop(&mut self) -> Option<T>;
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
 }
+
+/// producing half of a queue shared between two threads
+pub trait FifoProducer<T> {
+    fn push(&mut self, new_data: T);
+    /// a frame handed back by the consumer to be reused, its content is stale
+    fn take_recycled(&mut self) -> Option<T>;
+}
+
+/// consuming half of a queue shared between two threads
+pub trait FifoConsumer<T> {
+    fn pop(&mut self) -> Option<T>;
+    /// hands a popped frame back to the producer to be reused
+    fn recycle(&mut self, frame: T);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    /// number of frames dropped because the queue was full
+    fn dropped(&self) -> usize;
+}
+
+/// what a full queue does with a new frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OverflowPolicy {
+    /// the oldest frame is dropped, for live displays
+    #[default]
+    DropOldest,
+    /// the new frame is dropped
+    DropNewest,
+    /// the producer waits up to the timeout for the consumer,
+    /// drops the new frame if it is still full afterwards
+    Block(Duration),
+    /// only every nth new frame is kept while full, replacing the oldest frame
+    Decimate(usize),
+}
+
+impl OverflowPolicy {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+    pub const DEFAULT_DECIMATION: usize = 2;
+
+    pub fn values() -> [OverflowPolicy; 4] {
+        static OVERFLOW_POLICIES: [OverflowPolicy; 4] = [
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::Block(OverflowPolicy::DEFAULT_TIMEOUT),
+            OverflowPolicy::Decimate(OverflowPolicy::DEFAULT_DECIMATION),
+        ];
+        OVERFLOW_POLICIES
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = &'static str;
+
+    /// `"dropoldest"`, `"dropnewest"`, `"block"`, `"block:<timeout in ms>"`,
+    /// `"decimate"` or `"decimate:<n>"`, `-` and `_` are ignored
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase().replace(['-', '_'], "");
+        let mut parts = lower.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let parameter = match parts.next() {
+            Some(parameter) => Some(parameter.parse::<u64>().map_err(|_| "invalid parameter")?),
+            None => None,
+        };
+        match name {
+            "dropoldest" | "oldest" if parameter.is_none() => Ok(OverflowPolicy::DropOldest),
+            "dropnewest" | "newest" if parameter.is_none() => Ok(OverflowPolicy::DropNewest),
+            "block" => Ok(OverflowPolicy::Block(
+                parameter.map_or(OverflowPolicy::DEFAULT_TIMEOUT, Duration::from_millis),
+            )),
+            "decimate" => {
+                match parameter.map_or(OverflowPolicy::DEFAULT_DECIMATION, |n| n as usize) {
+                    0 => Err("invalid parameter"),
+                    decimation => Ok(OverflowPolicy::Decimate(decimation)),
+                }
+            }
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for OverflowPolicy {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OverflowPolicy::DropOldest => write!(formatter, "DropOldest"),
+            OverflowPolicy::DropNewest => write!(formatter, "DropNewest"),
+            OverflowPolicy::Block(timeout) => write!(formatter, "Block:{}", timeout.as_millis()),
+            OverflowPolicy::Decimate(decimation) => write!(formatter, "Decimate:{}", decimation),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::audio_file::AudioFile;
+use crate::stft_handler::ProducerRbf32;
+use crate::stream_stats::{StreamStats, StreamTime};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// number of frames pushed into the ringbuffers at once, like a jack period
+const PERIOD_SIZE: usize = 1024;
+/// time a `Pacing::Fast` source sleeps while a ringbuffer is full
+const FULL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// how fast a `FileSource` feeds its ringbuffers
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Pacing {
+    /// as fast as the ringbuffers are drained, waits while they are full
+    /// and drops nothing, for batch analysis
+    #[default]
+    Fast,
+    /// at the sample rate of the file like a live input,
+    /// samples which do not fit into a full ringbuffer are dropped
+    RealTime,
+}
+
+impl FromStr for Pacing {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        match &lower[..] {
+            "fast" => Ok(Pacing::Fast),
+            "batch" => Ok(Pacing::Fast),
+            "realtime" => Ok(Pacing::RealTime),
+            "real-time" => Ok(Pacing::RealTime),
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for Pacing {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+impl Pacing {
+    pub fn values() -> [Pacing; 2] {
+        static PACINGS: [Pacing; 2] = [Pacing::Fast, Pacing::RealTime];
+        PACINGS
+    }
+}
+
+/// plays an `AudioFile` into one ringbuffer per channel from its own thread,
+/// in place of the jack client, e.g. to analyze recordings without a jack server.
+/// the thread stops at the end of the file or when the source is dropped
+pub struct FileSource {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    sample_rate: u32,
+}
+
+impl FileSource {
+    /// starts feeding ringbuffer n with channel n of `file`,
+    /// or with its last channel if the file has less channels.
+    /// like the jack thread it reports the time of every period and counts
+    /// dropped samples into the stats of the ringbuffer.
+    /// the frame time is the position in the file, the wall time is
+    /// the time the position is played at in real time
+    /// # Panics
+    /// panics unless there are as many `stream_stats` as `ringbuffers_in`
+    pub fn start(
+        file: AudioFile,
+        mut ringbuffers_in: Vec<ProducerRbf32>,
+        stream_stats: Vec<Arc<StreamStats>>,
+        pacing: Pacing,
+    ) -> Self {
+        assert_eq!(ringbuffers_in.len(), stream_stats.len());
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let sample_rate = file.sample_rate;
+        let thread = thread::spawn(move || {
+            let channel_count = file.channel_count;
+            let frame_count = file.frame_count();
+            let start = Instant::now();
+            let wall_start = SystemTime::now();
+            // samples pushed into each ringbuffer so far
+            let mut pushed_samples: Vec<u64> = vec![0; ringbuffers_in.len()];
+            let mut position = 0;
+            while position < frame_count && thread_running.load(Ordering::Relaxed) {
+                let end = (position + PERIOD_SIZE).min(frame_count);
+                let offset = Duration::from_secs_f64(position as f64 / sample_rate as f64);
+                if pacing == Pacing::RealTime {
+                    let elapsed = start.elapsed();
+                    if offset > elapsed {
+                        thread::sleep(offset - elapsed);
+                    }
+                }
+                for (index, ((ringbuffer_in, stream_stats), pushed_samples)) in ringbuffers_in
+                    .iter_mut()
+                    .zip(stream_stats.iter())
+                    .zip(pushed_samples.iter_mut())
+                    .enumerate()
+                {
+                    stream_stats.set_time(StreamTime {
+                        sample_index: *pushed_samples,
+                        frame_time: position as u32,
+                        wall_time: wall_start + offset,
+                    });
+                    let channel = index.min(channel_count - 1);
+                    let mut samples = file.samples
+                        [position * channel_count + channel..end * channel_count]
+                        .iter()
+                        .step_by(channel_count)
+                        .copied();
+                    let mut pushed = ringbuffer_in.push_iter(&mut samples);
+                    if pacing == Pacing::Fast {
+                        while pushed < end - position && thread_running.load(Ordering::Relaxed) {
+                            thread::sleep(FULL_INTERVAL);
+                            pushed += ringbuffer_in.push_iter(&mut samples);
+                        }
+                    }
+                    stream_stats.add_dropped_samples(end - position - pushed);
+                    *pushed_samples += pushed as u64;
+                }
+                position = end;
+            }
+        });
+        Self {
+            running,
+            thread: Some(thread),
+            sample_rate,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// true once the whole file was pushed or the source was stopped
+    pub fn is_finished(&self) -> bool {
+        match &self.thread {
+            Some(thread) => thread.is_finished(),
+            None => true,
+        }
+    }
+
+    /// waits until the whole file was pushed,
+    /// with `Pacing::Fast` the ringbuffers have to be drained meanwhile
+    pub fn join(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// stops feeding the ringbuffers and waits for the thread
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        self.join();
+    }
+}
+
+impl Drop for FileSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -1,4 +1,17 @@
+pub mod audio_file;
+pub mod broadcast;
+pub mod chroma;
+pub mod colormap;
+pub mod cqt;
+pub mod cross_spectrum;
+pub mod dual_channel_handler;
 pub mod fifo_queue;
+pub mod file_source;
+pub mod mel;
 pub mod spectrum_queue;
+pub mod spsc_queue;
+pub mod stereo;
+pub mod stereo_handler;
 pub mod stft;
 pub mod stft_handler;
+pub mod stream_stats;
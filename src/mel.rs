@@ -0,0 +1,335 @@
+use crate::stft::FromF64;
+use std::str::FromStr;
+
+/// easy to use function to calc mel band energies
+///
+/// * `spectrogram` - linear magnitude columns, e.g. of
+///   `calculate_stft_with_scaling(.., ScalingMode::Linear)`
+/// * `filterbank` - created for the fft size of the spectrogram
+/// * `return` - vector of mel band energies per column
+pub fn calculate_mel(spectrogram: &[Vec<f32>], filterbank: &MelFilterbank<f32>) -> Vec<Vec<f32>> {
+    let mut mel_spectrogram: Vec<Vec<f32>> = Vec::new();
+    let mut mel_column: Vec<f32> = vec![0.0; filterbank.output_size()];
+    for spectrogram_column in spectrogram {
+        filterbank.compute(spectrogram_column, &mut mel_column[..]);
+        mel_spectrogram.push(mel_column.clone());
+    }
+    mel_spectrogram
+}
+
+/// easy to use function to calc mfccs, see `calculate_mel` for the input
+pub fn calculate_mfcc(
+    spectrogram: &[Vec<f32>],
+    filterbank: &MelFilterbank<f32>,
+    mfcc: &MFCC<f32>,
+) -> Vec<Vec<f32>> {
+    let mut mfcc_spectrogram: Vec<Vec<f32>> = Vec::new();
+    let mut mfcc_column: Vec<f32> = vec![0.0; mfcc.output_size()];
+    for mel_column in calculate_mel(spectrogram, filterbank) {
+        mfcc.compute(&mel_column, &mut mfcc_column[..]);
+        mfcc_spectrogram.push(mfcc_column.clone());
+    }
+    mfcc_spectrogram
+}
+
+/// formula used to map between Hz and mel
+#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash, Default)]
+pub enum MelScale {
+    /// `2595 * log10(1 + f / 700)`, triangular filters with a peak of 1.0
+    #[default]
+    Htk,
+    /// linear below 1 kHz and logarithmic above (auditory toolbox / librosa default),
+    /// triangular filters normalized to equal area
+    Slaney,
+}
+
+impl FromStr for MelScale {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        match &lower[..] {
+            "htk" => Ok(MelScale::Htk),
+            "slaney" => Ok(MelScale::Slaney),
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for MelScale {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+impl MelScale {
+    const SLANEY_MIN_LOG_HZ: f64 = 1000.0;
+    const SLANEY_HZ_PER_MEL: f64 = 200.0 / 3.0;
+
+    fn slaney_log_step() -> f64 {
+        6.4f64.ln() / 27.0
+    }
+
+    pub fn hz_to_mel(&self, frequency: f64) -> f64 {
+        match self {
+            MelScale::Htk => 2595.0 * (1.0 + frequency / 700.0).log10(),
+            MelScale::Slaney => {
+                let min_log_mel = Self::SLANEY_MIN_LOG_HZ / Self::SLANEY_HZ_PER_MEL;
+                if frequency < Self::SLANEY_MIN_LOG_HZ {
+                    frequency / Self::SLANEY_HZ_PER_MEL
+                } else {
+                    min_log_mel
+                        + (frequency / Self::SLANEY_MIN_LOG_HZ).ln() / Self::slaney_log_step()
+                }
+            }
+        }
+    }
+
+    pub fn mel_to_hz(&self, mel: f64) -> f64 {
+        match self {
+            MelScale::Htk => 700.0 * (10f64.powf(mel / 2595.0) - 1.0),
+            MelScale::Slaney => {
+                let min_log_mel = Self::SLANEY_MIN_LOG_HZ / Self::SLANEY_HZ_PER_MEL;
+                if mel < min_log_mel {
+                    mel * Self::SLANEY_HZ_PER_MEL
+                } else {
+                    Self::SLANEY_MIN_LOG_HZ * (Self::slaney_log_step() * (mel - min_log_mel)).exp()
+                }
+            }
+        }
+    }
+}
+
+/// one triangular filter, weights start at fft bin `start`
+pub struct MelFilter<T> {
+    pub start: usize,
+    pub weights: Vec<T>,
+}
+
+/// triangular filters spaced equally on the mel scale,
+/// applied to linear magnitude columns of an `STFT`
+pub struct MelFilterbank<T> {
+    pub band_count: usize,
+    pub min_frequency: f32,
+    pub max_frequency: f32,
+    pub scale: MelScale,
+    pub filters: Vec<MelFilter<T>>,
+}
+
+impl<T> MelFilterbank<T>
+where
+    T: num::Float + FromF64,
+{
+    /// * `band_count` - number of mel bands
+    /// * `min_frequency` - lower edge of the first band in Hz
+    /// * `max_frequency` - upper edge of the last band in Hz, at most nyquist
+    /// * `sample_rate` - sample rate of the analyzed signal in Hz
+    /// * `fft_size` - fft size of the `STFT` the columns come from
+    pub fn new(
+        band_count: usize,
+        min_frequency: f32,
+        max_frequency: f32,
+        sample_rate: f32,
+        fft_size: usize,
+        scale: MelScale,
+    ) -> Self {
+        assert!(band_count > 0);
+        assert!(min_frequency >= 0.0);
+        assert!(min_frequency < max_frequency);
+        assert!(max_frequency <= sample_rate / 2.0);
+
+        let min_mel = scale.hz_to_mel(min_frequency as f64);
+        let max_mel = scale.hz_to_mel(max_frequency as f64);
+        // band edges: left edge, center, right edge of each band
+        let edges: Vec<f64> = (0..band_count + 2)
+            .map(|index| {
+                let mel = min_mel + (max_mel - min_mel) * index as f64 / (band_count + 1) as f64;
+                scale.mel_to_hz(mel)
+            })
+            .collect();
+        let bin_width = sample_rate as f64 / fft_size as f64;
+        let bin_count = fft_size / 2 + 1;
+
+        let filters = edges
+            .windows(3)
+            .map(|edge| {
+                let (left, center, right) = (edge[0], edge[1], edge[2]);
+                let area_norm = match scale {
+                    MelScale::Htk => 1.0,
+                    MelScale::Slaney => 2.0 / (right - left),
+                };
+                let start = ((left / bin_width).ceil() as usize).min(bin_count);
+                let end = ((right / bin_width).floor() as usize + 1).min(bin_count);
+                let weights = (start..end.max(start))
+                    .map(|bin| {
+                        let frequency = bin as f64 * bin_width;
+                        let weight = if frequency <= center {
+                            (frequency - left) / (center - left)
+                        } else {
+                            (right - frequency) / (right - center)
+                        };
+                        FromF64::from_f64(weight.max(0.0) * area_norm)
+                    })
+                    .collect();
+                MelFilter { start, weights }
+            })
+            .collect();
+
+        MelFilterbank {
+            band_count,
+            min_frequency,
+            max_frequency,
+            scale,
+            filters,
+        }
+    }
+
+    #[inline]
+    pub fn output_size(&self) -> usize {
+        self.band_count
+    }
+
+    /// center frequency in Hz of `band`
+    pub fn band_frequency(&self, band: usize) -> f32 {
+        let min_mel = self.scale.hz_to_mel(self.min_frequency as f64);
+        let max_mel = self.scale.hz_to_mel(self.max_frequency as f64);
+        let mel = min_mel + (max_mel - min_mel) * (band + 1) as f64 / (self.band_count + 1) as f64;
+        self.scale.mel_to_hz(mel) as f32
+    }
+
+    /// computes the mel band energies (filtered power) of a linear magnitude column,
+    /// bins missing in `magnitudes` (e.g. nyquist) are treated as zero
+    /// # Panics
+    /// panics unless `self.output_size() == output.len()`
+    pub fn compute(&self, magnitudes: &[T], output: &mut [T]) {
+        assert_eq!(self.output_size(), output.len());
+
+        for (dst, filter) in output.iter_mut().zip(self.filters.iter()) {
+            let start = filter.start.min(magnitudes.len());
+            *dst = magnitudes[start..]
+                .iter()
+                .zip(filter.weights.iter())
+                .fold(T::zero(), |acc, (&magnitude, &weight)| {
+                    acc + magnitude * magnitude * weight
+                });
+        }
+    }
+}
+
+/// mel frequency cepstral coefficients of mel band energies:
+/// natural log, orthonormal DCT-II and optional sinusoidal liftering
+pub struct MFCC<T> {
+    pub band_count: usize,
+    pub coefficient_count: usize,
+    /// liftering parameter `L`, `0` disables liftering
+    pub lifter: usize,
+    /// `coefficient_count` rows of `band_count` dct weights, lifter included
+    pub dct: Vec<Vec<T>>,
+}
+
+impl<T> MFCC<T>
+where
+    T: num::Float + FromF64,
+{
+    /// energies below this are clamped before taking the log
+    pub const MIN_ENERGY: f64 = 1e-10;
+
+    pub fn new(band_count: usize, coefficient_count: usize, lifter: usize) -> Self {
+        assert!(coefficient_count > 0);
+        assert!(coefficient_count <= band_count);
+
+        let dct = (0..coefficient_count)
+            .map(|coefficient| {
+                let norm = if coefficient == 0 {
+                    (1.0 / band_count as f64).sqrt()
+                } else {
+                    (2.0 / band_count as f64).sqrt()
+                };
+                let lift = if lifter > 0 {
+                    1.0 + (lifter as f64 / 2.0)
+                        * (std::f64::consts::PI * coefficient as f64 / lifter as f64).sin()
+                } else {
+                    1.0
+                };
+                (0..band_count)
+                    .map(|band| {
+                        let angle = std::f64::consts::PI * coefficient as f64 * (band as f64 + 0.5)
+                            / band_count as f64;
+                        FromF64::from_f64(norm * lift * angle.cos())
+                    })
+                    .collect()
+            })
+            .collect();
+
+        MFCC {
+            band_count,
+            coefficient_count,
+            lifter,
+            dct,
+        }
+    }
+
+    #[inline]
+    pub fn output_size(&self) -> usize {
+        self.coefficient_count
+    }
+
+    /// # Panics
+    /// panics unless `self.band_count == mel_energies.len()`
+    /// and `self.output_size() == output.len()`
+    pub fn compute(&self, mel_energies: &[T], output: &mut [T]) {
+        assert_eq!(self.band_count, mel_energies.len());
+        assert_eq!(self.output_size(), output.len());
+
+        let min_energy: T = FromF64::from_f64(Self::MIN_ENERGY);
+        for (dst, weights) in output.iter_mut().zip(self.dct.iter()) {
+            *dst = mel_energies
+                .iter()
+                .zip(weights.iter())
+                .fold(T::zero(), |acc, (&energy, &weight)| {
+                    acc + energy.max(min_energy).ln() * weight
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hz_mel_round_trip() {
+        for scale in [MelScale::Htk, MelScale::Slaney] {
+            for frequency in [0.0, 20.0, 440.0, 999.0, 1000.0, 1001.0, 8000.0, 22050.0] {
+                let round_trip = scale.mel_to_hz(scale.hz_to_mel(frequency));
+                assert!(
+                    (round_trip - frequency).abs() < 1e-6,
+                    "{scale}: {frequency} Hz became {round_trip} Hz"
+                );
+            }
+        }
+        // reference points of both scales
+        assert!((MelScale::Htk.hz_to_mel(1000.0) - 1000.0).abs() < 0.1);
+        assert!((MelScale::Slaney.hz_to_mel(1000.0) - 15.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mfcc_dct_is_orthonormal() {
+        let band_count = 40;
+        let mfcc = MFCC::<f64>::new(band_count, band_count, 0);
+        for (row, weights) in mfcc.dct.iter().enumerate() {
+            for (other_row, other_weights) in mfcc.dct.iter().enumerate() {
+                let dot: f64 = weights
+                    .iter()
+                    .zip(other_weights.iter())
+                    .map(|(a, b)| a * b)
+                    .sum();
+                let expected = if row == other_row { 1.0 } else { 0.0 };
+                assert!(
+                    (dot - expected).abs() < 1e-9,
+                    "rows {row} and {other_row}: {dot}"
+                );
+            }
+        }
+    }
+}
@@ -1,30 +1,76 @@
+use crossbeam_channel;
 use jack;
 
-use ringbuf::Producer;
-use ringbuf::SharedRb;
-use std::mem::MaybeUninit;
+use spectrogram_lib::stft_handler::ProducerRbf32;
+use spectrogram_lib::stream_stats::{StreamStats, StreamTime};
 use std::process::exit;
 use std::sync::Arc;
+use std::time::SystemTime;
 use std::{thread, time::Duration};
 
+/// names of `channel_count` input ports,
+/// stereo keeps the `_l` and `_r` suffixes and complex input uses `_i` and `_q`
+pub fn port_names(channel_count: usize, iq: bool) -> Vec<String> {
+    (0..channel_count)
+        .map(|channel| match (iq, channel_count, channel) {
+            (true, 2, 0) => "spectrogram_gui_i".to_string(),
+            (true, 2, 1) => "spectrogram_gui_q".to_string(),
+            (false, 2, 0) => "spectrogram_gui_l".to_string(),
+            (false, 2, 1) => "spectrogram_gui_r".to_string(),
+            _ => format!("spectrogram_gui_{}", channel + 1),
+        })
+        .collect()
+}
+
+/// counts xruns into the stats of every port
+struct Notifications {
+    stream_stats: Vec<Arc<StreamStats>>,
+}
+
+impl jack::NotificationHandler for Notifications {
+    fn xrun(&mut self, _: &jack::Client) -> jack::Control {
+        for (index, stream_stats) in self.stream_stats.iter().enumerate() {
+            // ports may share their stats, count once
+            if !self.stream_stats[..index]
+                .iter()
+                .any(|previous| Arc::ptr_eq(previous, stream_stats))
+            {
+                stream_stats.add_xrun();
+            }
+        }
+        jack::Control::Continue
+    }
+}
+
+/// starts the jack client thread with one input port per ringbuffer,
+/// samples dropped by a full ringbuffer and xruns are counted into the stats of its port,
+/// which also get the time of the first sample of every period.
+/// returns the thread handle and the sample rate of the jack server
 pub fn start_jack_thread(
-    mut ringbuffer_left_in: Producer<f32, Arc<SharedRb<f32, std::vec::Vec<MaybeUninit<f32>>>>>,
-    mut ringbuffer_right_in: Producer<f32, Arc<SharedRb<f32, std::vec::Vec<MaybeUninit<f32>>>>>,
-) -> std::thread::JoinHandle<()> {
-    std::thread::spawn(move || {
+    port_names: Vec<String>,
+    mut ringbuffers_in: Vec<ProducerRbf32>,
+    stream_stats: Vec<Arc<StreamStats>>,
+) -> (std::thread::JoinHandle<()>, usize) {
+    assert_eq!(port_names.len(), ringbuffers_in.len());
+    assert_eq!(port_names.len(), stream_stats.len());
+    let (tx_sample_rate, rx_sample_rate) = crossbeam_channel::bounded(1);
+    let jack_thread = std::thread::spawn(move || {
         let mut run: bool = true;
         let (client, _status) =
             jack::Client::new("spectrogram_gui", jack::ClientOptions::NO_START_SERVER)
                 .expect("No Jack server running\n");
 
         let sample_rate = client.sample_rate();
+        let _ = tx_sample_rate.send(sample_rate);
         // register ports
-        let in_a = client
-            .register_port("spectrogram_gui_l", jack::AudioIn::default())
-            .unwrap();
-        let in_b = client
-            .register_port("spectrogram_gui_r", jack::AudioIn::default())
-            .unwrap();
+        let in_ports: Vec<jack::Port<jack::AudioIn>> = port_names
+            .iter()
+            .map(|port_name| {
+                client
+                    .register_port(port_name, jack::AudioIn::default())
+                    .unwrap()
+            })
+            .collect();
 
         let frame_size = client.buffer_size() as usize;
         if client.set_buffer_size(frame_size as u32).is_ok() {
@@ -38,16 +84,37 @@ pub fn start_jack_thread(
             exit(-1);
         }
 
+        let notifications = Notifications {
+            stream_stats: stream_stats.clone(),
+        };
+        // samples pushed into each ringbuffer so far
+        let mut pushed_samples: Vec<u64> = vec![0; in_ports.len()];
         let process_callback = move |_: &jack::Client, ps: &jack::ProcessScope| -> jack::Control {
-            let in_a_p = in_a.as_slice(ps);
-            let in_b_p = in_b.as_slice(ps);
-
-            ringbuffer_left_in.push_iter(&mut in_a_p.iter().copied());
-            ringbuffer_right_in.push_iter(&mut in_b_p.iter().copied());
+            let frame_time = ps.last_frame_time();
+            let wall_time = SystemTime::now()
+                - Duration::from_secs_f64(
+                    ps.frames_since_cycle_start() as f64 / sample_rate as f64,
+                );
+            for (((in_port, ringbuffer_in), stream_stats), pushed_samples) in in_ports
+                .iter()
+                .zip(ringbuffers_in.iter_mut())
+                .zip(stream_stats.iter())
+                .zip(pushed_samples.iter_mut())
+            {
+                stream_stats.set_time(StreamTime {
+                    sample_index: *pushed_samples,
+                    frame_time,
+                    wall_time,
+                });
+                let in_port_p = in_port.as_slice(ps);
+                let pushed = ringbuffer_in.push_iter(&mut in_port_p.iter().copied());
+                stream_stats.add_dropped_samples(in_port_p.len() - pushed);
+                *pushed_samples += pushed as u64;
+            }
             jack::Control::Continue
         };
         let process = jack::ClosureProcessHandler::new(process_callback);
-        let active_client = client.activate_async((), process).unwrap();
+        let active_client = client.activate_async(notifications, process).unwrap();
 
         while run {
             thread::sleep(Duration::from_millis(100));
@@ -61,5 +128,9 @@ pub fn start_jack_thread(
             Ok(_) => println!("exit audio thread\n"),
             Err(_) => println!("exit audio thread,client deactivation err\n"),
         }
-    })
+    });
+    let sample_rate = rx_sample_rate
+        .recv()
+        .expect("jack thread exited before reporting the sample rate");
+    (jack_thread, sample_rate)
 }
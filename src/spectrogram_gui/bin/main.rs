@@ -1,24 +1,149 @@
 mod spectrogram_gui;
-use spectrogram_gui::SpectrogramGui;
+use spectrogram_gui::{SpectrogramGui, RINGBUFFER_SIZE};
 mod jackprocess;
-use jackprocess::start_jack_thread;
+mod worker;
+use jackprocess::{port_names, start_jack_thread};
 use ringbuf::HeapRb;
+use spectrogram_lib::audio_file::AudioFile;
+use spectrogram_lib::dual_channel_handler::DualChannelHandler;
+use spectrogram_lib::file_source::{FileSource, Pacing};
+use spectrogram_lib::stereo::{DerivedChannel, InterChannel};
+use spectrogram_lib::stereo_handler::StereoHandler;
+use spectrogram_lib::stft::WindowType;
 use spectrogram_lib::stft_handler::StftHandler;
+use spectrogram_lib::stream_stats::StreamStats;
+use std::sync::Arc;
+
+/// number of jack input ports if not given as first argument,
+/// two ports are analyzed as a stereo pair
+const DEFAULT_CHANNEL_COUNT: usize = 2;
 
 fn main() {
-    let ringbuffer_left = HeapRb::<f32>::new(96000);
-    let ringbuffer_right = HeapRb::<f32>::new(96000);
+    // "iq" analyzes two ports as the real and imaginary part of a complex signal
+    let iq = std::env::args().nth(1).as_deref() == Some("iq");
+    // a wav or flac file is played in real time instead of reading jack ports
+    let mut audio_file = None;
+    let channel_count = match std::env::args().nth(1) {
+        Some(_) if iq => 2,
+        Some(argument) => match argument.parse::<usize>() {
+            Ok(channel_count) if channel_count > 0 => channel_count,
+            Ok(_) => panic!("usage: spectrogram_gui [channel_count | iq | file]"),
+            Err(_) => {
+                let file = AudioFile::open(&argument).expect("cannot open audio file");
+                let channel_count = file.channel_count;
+                audio_file = Some(file);
+                channel_count
+            }
+        },
+        None => DEFAULT_CHANNEL_COUNT,
+    };
+
+    let mut ringbuffers_in = Vec::new();
+    let mut ringbuffers_out = Vec::new();
+    for _ in 0..channel_count {
+        let ringbuffer = HeapRb::<f32>::new(RINGBUFFER_SIZE);
+        let (ringbuffer_in, ringbuffer_out) = ringbuffer.split();
+        ringbuffers_in.push(ringbuffer_in);
+        ringbuffers_out.push(ringbuffer_out);
+    }
+    // a stereo pair and complex input are analyzed together and share their counters
+    let stream_stats: Vec<Arc<StreamStats>> = if channel_count == 2 {
+        let shared_stats = Arc::new(StreamStats::new());
+        vec![shared_stats.clone(), shared_stats]
+    } else {
+        (0..channel_count)
+            .map(|_| Arc::new(StreamStats::new()))
+            .collect()
+    };
+    let mut jack_thread = None;
+    let mut file_source = None;
+    let sample_rate = match audio_file {
+        Some(file) => {
+            let source =
+                FileSource::start(file, ringbuffers_in, stream_stats.clone(), Pacing::RealTime);
+            let sample_rate = source.sample_rate() as usize;
+            file_source = Some(source);
+            sample_rate
+        }
+        None => {
+            let (thread, sample_rate) = start_jack_thread(
+                port_names(channel_count, iq),
+                ringbuffers_in,
+                stream_stats.clone(),
+            );
+            jack_thread = Some(thread);
+            sample_rate
+        }
+    };
+
+    // complex input needs a single handler reading both ports
+    let mut ringbuffer_q_out = None;
+    if iq {
+        ringbuffer_q_out = ringbuffers_out.pop();
+    }
 
-    let (ringbuffer_left_in, ringbuffer_left_out) = ringbuffer_left.split();
-    let (ringbuffer_right_in, ringbuffer_right_out) = ringbuffer_right.split();
-    let stft_handler = StftHandler::new(ringbuffer_left_out);
-    let jack_thread = start_jack_thread(ringbuffer_left_in, ringbuffer_right_in);
+    // stereo input is split into left, right, mid and side,
+    // left is the reference and right the measurement of the transfer function
+    let mut stereo_handler = None;
+    let mut dual_channel_handler = None;
+    if channel_count == 2 && !iq {
+        let ringbuffer_right_out = ringbuffers_out.pop().unwrap();
+        let ringbuffer_left_out = ringbuffers_out.pop().unwrap();
+        let mut stereo = StereoHandler::new(ringbuffer_left_out, ringbuffer_right_out);
+        stereo.set_sample_rate(sample_rate as f32);
+        stereo.set_stream_stats(stream_stats[0].clone());
+        stereo.set_inter_channel(
+            Some(InterChannel::default()),
+            WindowType::Hanning,
+            512,
+            1024,
+            256,
+        );
+        for channel in DerivedChannel::values() {
+            ringbuffers_out.push(stereo.add_channel(channel, RINGBUFFER_SIZE));
+        }
+        let mut dual_channel = DualChannelHandler::new(
+            stereo.add_channel(DerivedChannel::Left, RINGBUFFER_SIZE),
+            stereo.add_channel(DerivedChannel::Right, RINGBUFFER_SIZE),
+        );
+        dual_channel.set_sample_rate(sample_rate as f32);
+        dual_channel_handler = Some(dual_channel);
+        stereo_handler = Some(stereo);
+    }
+    let mut stft_handlers: Vec<StftHandler> = ringbuffers_out
+        .into_iter()
+        .enumerate()
+        .map(|(channel, ringbuffer_out)| {
+            let mut stft_handler = StftHandler::new(ringbuffer_out);
+            stft_handler
+                .set_sample_rate(sample_rate as f32)
+                .expect("no features are set yet");
+            // derived stereo channels share the counters of the stereo pair
+            let channel_stats = stream_stats.get(channel).unwrap_or(&stream_stats[0]);
+            stft_handler.set_stream_stats(channel_stats.clone());
+            stft_handler
+        })
+        .collect();
+    if let Some(stft_handler) = stft_handlers.first_mut() {
+        stft_handler.set_quadrature_input(ringbuffer_q_out);
+    }
+    // columns are computed in worker threads, the gui only draws them.
+    // the workers are stopped when the handlers are dropped with the gui
+    for stft_handler in stft_handlers.iter_mut() {
+        stft_handler.start_worker();
+    }
 
-    let stft_handlers = vec![stft_handler];
-    let spectrogram_app = SpectrogramGui::new(stft_handlers);
-    //    spectrogram_app.set_ringbuffer(ringbuffer_left_out, ringbuffer_right_out);
+    let stft_handler_count = stft_handlers.len().min(channel_count);
+    let mut spectrogram_app = SpectrogramGui::new(stft_handlers);
+    if let Some(stereo_handler) = stereo_handler {
+        spectrogram_app.set_stereo_handler(stereo_handler);
+    }
+    if let Some(dual_channel_handler) = dual_channel_handler {
+        spectrogram_app.set_dual_channel_handler(dual_channel_handler);
+    }
     let mut options = eframe::NativeOptions::default();
-    let window_size: eframe::egui::Vec2 = eframe::egui::Vec2::new(525.0, 530.0);
+    let window_size: eframe::egui::Vec2 =
+        eframe::egui::Vec2::new(525.0 * stft_handler_count as f32, 580.0);
     options.initial_window_size = Some(window_size);
 
     eframe::run_native(
@@ -26,5 +151,8 @@ fn main() {
         options,
         Box::new(|_cc| Box::new(spectrogram_app)),
     );
-    jack_thread.join().unwrap();
+    drop(file_source);
+    if let Some(jack_thread) = jack_thread {
+        jack_thread.join().unwrap();
+    }
 }
@@ -1,3 +1,4 @@
+use crate::worker::Worker;
 use eframe::egui;
 use eframe::egui::{lerp, Color32, Rgba, TextureHandle};
 use egui::plot::{GridInput, GridMark};
@@ -5,51 +6,469 @@ use egui::*;
 use itertools::izip;
 use plot::{
     Arrows, Bar, BarChart, CoordinatesFormatter, Corner, HLine, Legend, Line, LineStyle,
-    MarkerShape, Plot, PlotImage, Points, Polygon, Text, VLine,
+    MarkerShape, Plot, PlotImage, Points, Polygon, Text, VLine, Value, Values,
 };
-use spectrogram_lib::stft_handler::StftHandler;
+use spectrogram_lib::chroma::{pitch_class_name, Chroma};
+use spectrogram_lib::colormap::{self, Colormap};
+use spectrogram_lib::cqt::CQT;
+use spectrogram_lib::cross_spectrum::Averaging;
+use spectrogram_lib::dual_channel_handler::DualChannelHandler;
+use spectrogram_lib::stereo::DerivedChannel;
+use spectrogram_lib::stereo_handler::StereoHandler;
+use spectrogram_lib::stft::{
+    FftMode, ScalingMode, SpectrogramMode, WindowNormalization, WindowType,
+};
+use spectrogram_lib::stft_handler::{FeatureMode, StftHandler, TimedColumn};
+use std::collections::VecDeque;
 use std::f32;
 use std::f64::consts::TAU;
 use std::ops::RangeInclusive;
+use std::time::SystemTime;
+
+/// number of labels on the frequency axis
+const FREQUENCY_TICKS: usize = 4;
+/// height of the frequency axis below each spectrogram
+const AXIS_HEIGHT: f32 = 14.0;
+/// number of labels on the time axis
+const TIME_TICKS: usize = 5;
+/// width of the time axis right of each spectrogram
+const TIME_AXIS_WIDTH: f32 = 40.0;
+/// lowest constant-Q bin, C1
+const CQT_MIN_FREQUENCY: f32 = 32.703;
+const CQT_BINS_PER_OCTAVE: usize = 36;
+const CQT_OCTAVES: usize = 8;
+/// capacity of the audio ringbuffers in samples,
+/// holds the constant-Q window at sample rates up to 96 kHz
+pub const RINGBUFFER_SIZE: usize = 1 << 18;
+/// frequency range used for the chromagram, A1 to about D8
+const CHROMA_MIN_FREQUENCY: f32 = 55.0;
+const CHROMA_MAX_FREQUENCY: f32 = 5000.0;
+/// window and fft sizes selectable in the controls
+const STFT_SIZES: [usize; 6] = [256, 512, 1024, 2048, 4096, 8192];
+const STEP_SIZES: [usize; 7] = [64, 128, 256, 512, 1024, 2048, 4096];
+
+/// what is drawn from the stft columns
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum View {
+    Spectrogram,
+    Chromagram,
+}
+
+impl std::fmt::Display for View {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+/// how the channels are arranged
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Layout {
+    SideBySide,
+    Stacked,
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+/// which channels of a `StereoHandler` are shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum StereoView {
+    LeftRight,
+    MidSide,
+    /// level and phase difference between left and right
+    InterChannel,
+    /// transfer function and coherence from left (reference) to right (measurement)
+    TransferFunction,
+}
+
+impl std::fmt::Display for StereoView {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+impl StereoView {
+    fn shows(&self, channel: DerivedChannel) -> bool {
+        match self {
+            StereoView::LeftRight => {
+                channel == DerivedChannel::Left || channel == DerivedChannel::Right
+            }
+            StereoView::MidSide => {
+                channel == DerivedChannel::Mid || channel == DerivedChannel::Side
+            }
+            StereoView::InterChannel | StereoView::TransferFunction => false,
+        }
+    }
+}
+
+/// settings of one channel's `StftHandler` shown in the controls
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct ChannelSettings {
+    view: View,
+    window_type: WindowType,
+    window_size: usize,
+    fft_size: usize,
+    step_size: usize,
+    scaling: ScalingMode,
+    normalization: WindowNormalization,
+    mode: SpectrogramMode,
+    fft_mode: FftMode,
+    use_cqt: bool,
+}
+
+/// `edited` if it was changed from `shown`, else `own`
+fn pick<T: PartialEq + Copy>(own: T, shown: T, edited: T) -> T {
+    if edited != shown {
+        edited
+    } else {
+        own
+    }
+}
+
+impl ChannelSettings {
+    fn from_handler(stft_handle: &StftHandler) -> Self {
+        let config = stft_handle.config();
+        Self {
+            view: View::Spectrogram,
+            window_type: config.window_type,
+            window_size: config.window_size,
+            fft_size: config.fft_size,
+            step_size: config.step_size,
+            scaling: config.scaling,
+            normalization: config.normalization,
+            mode: config.mode,
+            fft_mode: config.fft_mode,
+            use_cqt: stft_handle.has_cqt(),
+        }
+    }
 
+    /// takes the fields changed from `shown` to `edited`, keeps the others of `self`.
+    /// fft and step size follow the window size if they don't fit
+    fn merge(&self, shown: &ChannelSettings, edited: &ChannelSettings) -> Self {
+        let window_size = pick(self.window_size, shown.window_size, edited.window_size);
+        let fft_size = pick(self.fft_size, shown.fft_size, edited.fft_size).max(window_size);
+        let mut step_size = pick(self.step_size, shown.step_size, edited.step_size);
+        if step_size > window_size {
+            step_size = window_size / 2;
+        }
+        Self {
+            view: pick(self.view, shown.view, edited.view),
+            window_type: pick(self.window_type, shown.window_type, edited.window_type),
+            window_size,
+            fft_size,
+            step_size,
+            scaling: pick(self.scaling, shown.scaling, edited.scaling),
+            normalization: pick(
+                self.normalization,
+                shown.normalization,
+                edited.normalization,
+            ),
+            mode: pick(self.mode, shown.mode, edited.mode),
+            fft_mode: pick(self.fft_mode, shown.fft_mode, edited.fft_mode),
+            use_cqt: pick(self.use_cqt, shown.use_cqt, edited.use_cqt),
+        }
+    }
+
+    /// applies the fields which differ from `previous` to `stft_handle`,
+    /// stft changes rebuild features and the constant-Q transform.
+    /// `use_cqt` is cleared if the constant-Q transform does not fit the ringbuffer
+    fn apply(&mut self, previous: &ChannelSettings, stft_handle: &mut StftHandler) {
+        let stft_changed = self.window_type != previous.window_type
+            || self.window_size != previous.window_size
+            || self.fft_size != previous.fft_size
+            || self.step_size != previous.step_size;
+        if stft_changed {
+            let config = stft_handle
+                .config()
+                .with_window_type(self.window_type)
+                .with_window_size(self.window_size)
+                .with_fft_size(self.fft_size)
+                .with_step_size(self.step_size);
+            stft_handle
+                .reconfigure(config)
+                .expect("merged settings are a valid stft config");
+        }
+        if self.view != previous.view || stft_changed {
+            let features = match self.view {
+                View::Spectrogram => None,
+                View::Chromagram => Some(FeatureMode::Chroma(Chroma::new(
+                    stft_handle.sample_rate(),
+                    stft_handle.fft_size(),
+                    12,
+                    Chroma::<f32>::DEFAULT_REFERENCE_FREQUENCY,
+                    CHROMA_MIN_FREQUENCY,
+                    CHROMA_MAX_FREQUENCY,
+                ))),
+            };
+            stft_handle.set_features(features);
+        }
+        if self.scaling != previous.scaling {
+            stft_handle.set_scaling(self.scaling);
+        }
+        if self.normalization != previous.normalization {
+            stft_handle.set_normalization(self.normalization);
+        }
+        if self.mode != previous.mode {
+            stft_handle.set_mode(self.mode);
+        }
+        if self.fft_mode != previous.fft_mode {
+            stft_handle.set_fft_mode(self.fft_mode);
+        }
+        if self.use_cqt != previous.use_cqt || (self.use_cqt && stft_changed) {
+            let cqt = if self.use_cqt {
+                Some(make_cqt(stft_handle))
+            } else {
+                None
+            };
+            if let Err(error) = stft_handle.set_cqt(cqt) {
+                eprintln!("{}", error);
+                self.use_cqt = false;
+                // the stft fits any ringbuffer
+                let _ = stft_handle.set_cqt(None);
+            }
+        }
+    }
+
+    fn stft_ui(&mut self, ui: &mut Ui) {
+        egui::ComboBox::from_label("window")
+            .selected_text(self.window_type.to_string())
+            .show_ui(ui, |ui| {
+                for window_type in WindowType::values() {
+                    ui.selectable_value(
+                        &mut self.window_type,
+                        window_type,
+                        window_type.to_string(),
+                    );
+                }
+            });
+        egui::ComboBox::from_label("window size")
+            .selected_text(self.window_size.to_string())
+            .show_ui(ui, |ui| {
+                for window_size in STFT_SIZES {
+                    ui.selectable_value(
+                        &mut self.window_size,
+                        window_size,
+                        window_size.to_string(),
+                    );
+                }
+            });
+        egui::ComboBox::from_label("fft size")
+            .selected_text(self.fft_size.to_string())
+            .show_ui(ui, |ui| {
+                for fft_size in STFT_SIZES
+                    .into_iter()
+                    .filter(|&size| size >= self.window_size)
+                {
+                    ui.selectable_value(&mut self.fft_size, fft_size, fft_size.to_string());
+                }
+            });
+        egui::ComboBox::from_label("fft")
+            .selected_text(self.fft_mode.to_string())
+            .show_ui(ui, |ui| {
+                for fft_mode in FftMode::values() {
+                    ui.selectable_value(&mut self.fft_mode, fft_mode, fft_mode.to_string());
+                }
+            });
+        egui::ComboBox::from_label("hop")
+            .selected_text(self.step_size.to_string())
+            .show_ui(ui, |ui| {
+                for step_size in STEP_SIZES
+                    .into_iter()
+                    .filter(|&size| size <= self.window_size)
+                {
+                    ui.selectable_value(&mut self.step_size, step_size, step_size.to_string());
+                }
+            });
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        egui::ComboBox::from_label("view")
+            .selected_text(self.view.to_string())
+            .show_ui(ui, |ui| {
+                for view_option in [View::Spectrogram, View::Chromagram] {
+                    ui.selectable_value(&mut self.view, view_option, view_option.to_string());
+                }
+            });
+        egui::ComboBox::from_label("scaling")
+            .selected_text(self.scaling.to_string())
+            .show_ui(ui, |ui| {
+                for scaling_mode in ScalingMode::values() {
+                    ui.selectable_value(&mut self.scaling, scaling_mode, scaling_mode.to_string());
+                }
+            });
+        egui::ComboBox::from_label("normalization")
+            .selected_text(self.normalization.to_string())
+            .show_ui(ui, |ui| {
+                for normalization_mode in WindowNormalization::values() {
+                    ui.selectable_value(
+                        &mut self.normalization,
+                        normalization_mode,
+                        normalization_mode.to_string(),
+                    );
+                }
+            });
+        egui::ComboBox::from_label("mode")
+            .selected_text(self.mode.to_string())
+            .show_ui(ui, |ui| {
+                for spectrogram_mode in SpectrogramMode::values() {
+                    ui.selectable_value(
+                        &mut self.mode,
+                        spectrogram_mode,
+                        spectrogram_mode.to_string(),
+                    );
+                }
+            });
+        ui.checkbox(&mut self.use_cqt, "constant-Q");
+    }
+}
+
+/// constant-Q transform from C1 up to `CQT_OCTAVES` octaves, limited by nyquist
+fn make_cqt(stft_handle: &StftHandler) -> CQT<f32> {
+    let sample_rate = stft_handle.sample_rate();
+    let max_frequency = 0.45 * sample_rate;
+    let available_bins =
+        (CQT_BINS_PER_OCTAVE as f32 * (max_frequency / CQT_MIN_FREQUENCY).log2()) as usize;
+    let bin_count = available_bins.min(CQT_OCTAVES * CQT_BINS_PER_OCTAVE);
+    CQT::new(
+        sample_rate,
+        CQT_MIN_FREQUENCY,
+        CQT_BINS_PER_OCTAVE,
+        bin_count,
+        stft_handle.config().step_size,
+    )
+}
+
+/// spectrogram texture of one channel
 //#[derive(PartialEq)]
 struct Spectrum {
     pub last_vec: Vec<f32>,
     pub tex_mngr: TextureManager,
-    pub texture_ids: Vec<Option<(egui::Vec2, egui::TextureId)>>,
+    /// number of frequency bins per spectrum, follows the fft size
+    pub bins: usize,
+    /// number of spectra kept in the texture
+    pub history: usize,
+    /// axis labels with their position in `0..=1` of the width
+    pub ticks: Vec<(f32, String)>,
+    /// wall time of every row of the texture, oldest first, `None` if unknown
+    pub times: VecDeque<Option<SystemTime>>,
 }
 
 impl Default for Spectrum {
     fn default() -> Self {
-        // TODO review this, maybe size of channels is important so no Default is possible
-        let texture_queue = vec![Color32::from_rgb(255, 255, 255); 1024 * 512];
-        let mut texture_queues = Vec::new();
-        let texture_ids = vec![None; 1];
-        texture_queues.push(texture_queue);
+        Self::new("spectrogram", 512)
+    }
+}
+
+impl Spectrum {
+    fn new(name: &str, bins: usize) -> Self {
+        let history = 512;
+        let texture_queue = vec![Color32::from_rgb(255, 255, 255); bins * history];
         Self {
-            last_vec: vec![0.0; 512],
-            tex_mngr: TextureManager(texture_queues, texture_ids),
-            texture_ids: Vec::new(),
+            last_vec: vec![0.0; bins],
+            tex_mngr: TextureManager(name.to_string(), texture_queue, None),
+            bins,
+            history,
+            ticks: Vec::new(),
+            times: VecDeque::from(vec![None; history]),
+        }
+    }
+
+    /// restarts the texture history if the number of bins changed
+    fn set_bins(&mut self, bins: usize) {
+        if bins != self.bins {
+            self.bins = bins;
+            self.last_vec = vec![0.0; bins];
+            self.tex_mngr.1 = vec![Color32::from_rgb(255, 255, 255); bins * self.history];
+            self.times = VecDeque::from(vec![None; self.history]);
+        }
+    }
+
+    /// times of the columns passed to the next `ui` call
+    fn add_times(&mut self, times: Vec<Option<SystemTime>>) {
+        self.times.extend(times);
+        while self.times.len() > self.history {
+            self.times.pop_front();
         }
     }
 }
 
 impl Spectrum {
-    fn ui(&mut self, ui: &mut Ui, spectrum_data: Vec<Vec<Vec<f32>>>) {
-        self.set_values(ui.ctx(), spectrum_data);
-        for texture_id in &self.texture_ids {
-            if let Some((size, texture_id)) = *texture_id {
-                ui.add(egui::Image::new(texture_id, size));
-                ui.ctx().request_repaint();
-            }
+    /// returns the drawn columns to be recycled
+    fn ui(&mut self, ui: &mut Ui, spectrum_data: Vec<Vec<f32>>, size: egui::Vec2) -> Vec<Vec<f32>> {
+        let spectrum_data = self.set_values(ui.ctx(), spectrum_data);
+        if let Some(ref texture) = self.tex_mngr.2 {
+            ui.horizontal(|ui| {
+                ui.add(egui::Image::new(texture, size));
+                self.time_axis(ui, size.y);
+            });
+            self.frequency_axis(ui, size.x);
+            ui.ctx().request_repaint();
         }
         // ui.horizontal(|ui| {});
         // self.bar_plot(ui);
+        spectrum_data
+    }
+
+    /// labels below the spectrogram, bins are laid out horizontally
+    fn frequency_axis(&self, ui: &mut Ui, width: f32) {
+        let (_, rect) = ui.allocate_space(egui::Vec2::new(width, AXIS_HEIGHT));
+        for (fraction, label) in self.ticks.iter() {
+            let fraction = *fraction;
+            let anchor = if fraction <= 0.0 {
+                Align2::LEFT_TOP
+            } else if fraction >= 1.0 {
+                Align2::RIGHT_TOP
+            } else {
+                Align2::CENTER_TOP
+            };
+            ui.painter().text(
+                egui::Pos2::new(lerp(rect.left()..=rect.right(), fraction), rect.top()),
+                anchor,
+                label,
+                FontId::proportional(10.0),
+                ui.visuals().text_color(),
+            );
+        }
+    }
+
+    /// age of the rows relative to the newest one, taken from the column timestamps
+    fn time_axis(&self, ui: &mut Ui, height: f32) {
+        let (_, rect) = ui.allocate_space(egui::Vec2::new(TIME_AXIS_WIDTH, height));
+        let newest = match self.times.back() {
+            Some(Some(newest)) => *newest,
+            _ => return,
+        };
+        for tick in 0..TIME_TICKS {
+            let fraction = tick as f32 / (TIME_TICKS - 1) as f32;
+            let row = ((self.times.len() - 1) as f32 * fraction).round() as usize;
+            let time = match self.times.get(row) {
+                Some(Some(time)) => *time,
+                _ => continue,
+            };
+            let age = newest.duration_since(time).unwrap_or_default();
+            let anchor = if fraction <= 0.0 {
+                Align2::LEFT_TOP
+            } else if fraction >= 1.0 {
+                Align2::LEFT_BOTTOM
+            } else {
+                Align2::LEFT_CENTER
+            };
+            ui.painter().text(
+                egui::Pos2::new(rect.left(), lerp(rect.top()..=rect.bottom(), fraction)),
+                anchor,
+                format!("-{:.1} s", age.as_secs_f32()),
+                FontId::proportional(10.0),
+                ui.visuals().text_color(),
+            );
+        }
     }
 
     fn bar_plot(&mut self, ui: &mut Ui) -> Response {
         let mut chart = BarChart::new(
-            (0..512)
+            (0..self.bins)
                 .step_by(1)
                 .map(|x| (x as f64, self.last_vec[x] as f64))
                 .map(|(x, f)| Bar::new(x, f.abs()).width(0.01))
@@ -60,126 +479,594 @@ impl Spectrum {
 
         Plot::new("Spectrum Demo")
             .legend(Legend::default())
-            .data_aspect(4.0 / 512.0)
+            .data_aspect(4.0 / self.bins as f32)
             .include_x(2.0)
-            .include_y(512.0)
+            .include_y(self.bins as f64)
             .width(100.0)
             .show(ui, |plot_ui| plot_ui.bar_chart(chart))
             .response
     }
 
-    fn set_values(&mut self, ctx: &egui::Context, specs_channels: Vec<Vec<Vec<f32>>>) {
-        let mut int_specs_channels: Vec<Vec<Vec<u8>>> = Vec::new();
-        for specs_channel in specs_channels.iter() {
-            let mut int_specs: Vec<Vec<u8>> = Vec::new();
-            for spec in specs_channel {
-                let int_spec = spec
-                    .iter()
-                    .map(|&value| (255.0 * value.abs()) as u8)
-                    .collect();
-                int_specs.push(int_spec);
-            }
-            int_specs_channels.push(int_specs);
+    fn set_values(&mut self, ctx: &egui::Context, specs: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+        if let Some(spec) = specs.first() {
+            self.set_bins(spec.len());
         }
-        /* TODO last_vec need to be vec of vectors, not in use (needed only for barplot)
-                for int_specs in int_specs_channels.iter().zip() {
-                        if specs_channel.len() > 0 {
-                                for (last, new) in self.last_vec.iter_mut().zip(&specs[specs.len() - 1]) {
-                *last = *new;
-            }
+        let mut int_specs: Vec<Vec<u8>> = Vec::new();
+        for spec in specs.iter() {
+            let int_spec = spec.iter().map(|&value| colormap::level(value)).collect();
+            int_specs.push(int_spec);
         }
-        */
-        self.tex_mngr
-            .update_spectrogram_texture(ctx, int_specs_channels, 512, 512);
-        for (texture_option, texture_id) in
-            self.tex_mngr.1.iter_mut().zip(self.texture_ids.iter_mut())
-        {
-            if let Some(ref texture) = *texture_option {
-                *texture_id = Some((egui::Vec2::new(512.0, 512.0), texture.into()));
-            }
+        if let Some(spec) = specs.last() {
+            self.last_vec.copy_from_slice(spec);
         }
+        self.tex_mngr
+            .update_spectrogram_texture(ctx, int_specs, self.bins, self.history);
+        specs
     }
 }
 
+/// pixel color of a gray level, the same mapping as `spectrogram_render`
 fn value_to_rgb(value: u8) -> egui::epaint::Color32 {
-    let min_value = 0.0;
-    let max_value = 255.0;
-    Color32::from_rgb(0, 0, 0)
+    let [red, green, blue] = Colormap::Gray.rgb(value);
+    Color32::from_rgb(red, green, blue)
 }
 
+/// texture name, pixel history and texture handle of one channel
 //#[derive(Default)]
-struct TextureManager(Vec<Vec<egui::epaint::Color32>>, Vec<Option<TextureHandle>>);
+struct TextureManager(String, Vec<egui::epaint::Color32>, Option<TextureHandle>);
 
 impl TextureManager {
     pub fn update_spectrogram_texture(
         &mut self,
         ctx: &egui::Context,
-        specs_channel: Vec<Vec<Vec<u8>>>,
+        specs: Vec<Vec<u8>>,
         width: usize,
         height: usize,
     ) {
-        for (specs, texture_queue, textures) in izip!(&specs_channel, &mut self.0, &mut self.1) {
-            let mut new_cols = specs
-                .iter()
-                .flatten()
-                .map(|x| egui::epaint::Color32::from_gray(*x))
-                .collect::<Vec<Color32>>();
-            texture_queue.append(&mut new_cols);
-            let current_length = texture_queue.len();
-            if current_length > width * height {
-                let drain_count = current_length - width * height;
-                texture_queue.drain(0..drain_count);
-            }
-            let pixels: Vec<egui::epaint::Color32> = texture_queue.clone();
-            *textures = Some(ctx.load_texture(
-                "color_test_gradient",
-                egui::ColorImage {
-                    size: [width, height],
-                    pixels,
-                },
-            ));
+        let texture_queue = &mut self.1;
+        let mut new_cols = specs
+            .iter()
+            .flatten()
+            .map(|x| value_to_rgb(*x))
+            .collect::<Vec<Color32>>();
+        texture_queue.append(&mut new_cols);
+        let current_length = texture_queue.len();
+        if current_length > width * height {
+            let drain_count = current_length - width * height;
+            texture_queue.drain(0..drain_count);
         }
-
-        // TODO handling of multiple texture handles
-        // maybe return an option
-        // or handle if pixels.len() < width*height
+        // TODO handle if pixels.len() < width*height
+        let pixels: Vec<egui::epaint::Color32> = texture_queue.clone();
+        self.2 = Some(ctx.load_texture(
+            &self.0,
+            egui::ColorImage {
+                size: [width, height],
+                pixels,
+            },
+        ));
     }
 }
 
 pub struct SpectrogramGui {
-    spectrum: Spectrum,
+    spectrums: Vec<Spectrum>,
     stft_handler: Option<Vec<StftHandler>>,
+    settings: Vec<ChannelSettings>,
+    channel_names: Vec<String>,
+    /// feeds the stft handlers with derived channels if set
+    stereo_handler: Option<Worker<StereoHandler>>,
+    stereo_view: StereoView,
+    /// level and phase difference images of the stereo handler
+    inter_channel_spectrums: Vec<Spectrum>,
+    dual_channel_handler: Option<Worker<DualChannelHandler>>,
+    /// channel edited by the controls, `None` edits all channels
+    selected_channel: Option<usize>,
+    layout: Layout,
 }
 
 impl SpectrogramGui {
     pub fn new(stft_handler: Vec<StftHandler>) -> Self {
+        let spectrums = stft_handler
+            .iter()
+            .enumerate()
+            .map(|(channel, stft_handle)| {
+                Spectrum::new(
+                    &format!("spectrogram_{}", channel),
+                    stft_handle.output_size(),
+                )
+            })
+            .collect();
+        let settings = stft_handler
+            .iter()
+            .map(ChannelSettings::from_handler)
+            .collect();
+        let channel_names = (0..stft_handler.len())
+            .map(|channel| format!("{}", channel + 1))
+            .collect();
         Self {
-            spectrum: Spectrum::default(),
+            spectrums,
             stft_handler: Some(stft_handler),
+            settings,
+            channel_names,
+            stereo_handler: None,
+            stereo_view: StereoView::LeftRight,
+            inter_channel_spectrums: Vec::new(),
+            dual_channel_handler: None,
+            selected_channel: None,
+            layout: Layout::SideBySide,
         }
     }
+
+    /// the stft handlers have to consume the first channels of `stereo_handler`
+    /// in the order they were added, it is run in its own thread
+    pub fn set_stereo_handler(&mut self, stereo_handler: StereoHandler) {
+        self.channel_names = stereo_handler
+            .channels()
+            .iter()
+            .take(self.settings.len())
+            .map(|channel| channel.to_string())
+            .collect();
+        self.inter_channel_spectrums = ["level_difference", "phase_difference"]
+            .iter()
+            .map(|name| Spectrum::new(name, stereo_handler.output_size()))
+            .collect();
+        self.stereo_handler = Some(Worker::start(stereo_handler, StereoHandler::run));
+    }
+
+    /// shown in the transfer function view, fed by the stereo handler,
+    /// it is run in its own thread
+    pub fn set_dual_channel_handler(&mut self, dual_channel_handler: DualChannelHandler) {
+        self.dual_channel_handler =
+            Some(Worker::start(dual_channel_handler, DualChannelHandler::run));
+    }
+
+    /// lost samples, columns and xruns per channel, highlighted once anything is lost
+    fn status_bar(&self, ui: &mut Ui) {
+        let stft_handler = match &self.stft_handler {
+            Some(stft_handler) => stft_handler,
+            None => return,
+        };
+        ui.horizontal_wrapped(|ui| {
+            for (channel_name, stft_handle) in self.channel_names.iter().zip(stft_handler.iter()) {
+                let dropped_samples = stft_handle.dropped_samples();
+                let dropped_columns = stft_handle.dropped_columns();
+                let xruns = stft_handle.xruns();
+                let text = format!(
+                    "{}: {} samples, {} columns dropped, {} xruns",
+                    channel_name, dropped_samples, dropped_columns, xruns
+                );
+                if dropped_samples > 0 || dropped_columns > 0 || xruns > 0 {
+                    ui.colored_label(Color32::RED, text);
+                } else {
+                    ui.label(text);
+                }
+            }
+        });
+    }
+
+    fn controls(&mut self, ui: &mut Ui) {
+        ui.horizontal_wrapped(|ui| {
+            let channel_names = &self.channel_names;
+            let channel_text = |channel: Option<usize>| match channel {
+                Some(channel) => channel_names[channel].clone(),
+                None => "all".to_string(),
+            };
+            egui::ComboBox::from_label("channel")
+                .selected_text(channel_text(self.selected_channel))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.selected_channel, None, channel_text(None));
+                    for channel in 0..self.settings.len() {
+                        ui.selectable_value(
+                            &mut self.selected_channel,
+                            Some(channel),
+                            channel_text(Some(channel)),
+                        );
+                    }
+                });
+            egui::ComboBox::from_label("layout")
+                .selected_text(self.layout.to_string())
+                .show_ui(ui, |ui| {
+                    for layout in [Layout::SideBySide, Layout::Stacked] {
+                        ui.selectable_value(&mut self.layout, layout, layout.to_string());
+                    }
+                });
+            if self.stereo_handler.is_some() {
+                egui::ComboBox::from_label("stereo")
+                    .selected_text(self.stereo_view.to_string())
+                    .show_ui(ui, |ui| {
+                        for stereo_view in [
+                            StereoView::LeftRight,
+                            StereoView::MidSide,
+                            StereoView::InterChannel,
+                            StereoView::TransferFunction,
+                        ] {
+                            ui.selectable_value(
+                                &mut self.stereo_view,
+                                stereo_view,
+                                stereo_view.to_string(),
+                            );
+                        }
+                    });
+            }
+
+            if self.stereo_view == StereoView::TransferFunction {
+                if let Some(dual_channel_handler) = &self.dual_channel_handler {
+                    let mut dual_channel_handler = dual_channel_handler.handler();
+                    let mut averaging = dual_channel_handler.averaging();
+                    egui::ComboBox::from_label("averaging")
+                        .selected_text(averaging.to_string())
+                        .show_ui(ui, |ui| {
+                            for averaging_mode in Averaging::values() {
+                                ui.selectable_value(
+                                    &mut averaging,
+                                    averaging_mode,
+                                    averaging_mode.to_string(),
+                                );
+                            }
+                        });
+                    if averaging != dual_channel_handler.averaging() {
+                        dual_channel_handler.set_averaging(averaging);
+                    }
+                    if ui.button("reset").clicked() {
+                        dual_channel_handler.reset();
+                    }
+                    ui.label(format!("{} averages", dual_channel_handler.count()));
+                }
+                return;
+            }
+
+            // "all" shows the settings of the first channel
+            let shown = match self.settings.get(self.selected_channel.unwrap_or(0)) {
+                Some(settings) => *settings,
+                None => return,
+            };
+            let mut edited = shown;
+            edited.ui(ui);
+            edited.stft_ui(ui);
+            if edited == shown {
+                return;
+            }
+            if let Some(stft_handler) = &mut self.stft_handler {
+                for (channel, (stft_handle, settings)) in stft_handler
+                    .iter_mut()
+                    .zip(self.settings.iter_mut())
+                    .enumerate()
+                {
+                    if self.selected_channel.is_some() && self.selected_channel != Some(channel) {
+                        continue;
+                    }
+                    let mut updated = settings.merge(&shown, &edited);
+                    updated.apply(settings, stft_handle);
+                    *settings = updated;
+                }
+            }
+        });
+    }
 }
 impl Default for SpectrogramGui {
     fn default() -> Self {
         Self {
-            spectrum: Spectrum::default(),
+            spectrums: Vec::new(),
             stft_handler: None,
+            settings: Vec::new(),
+            channel_names: Vec::new(),
+            stereo_handler: None,
+            stereo_view: StereoView::LeftRight,
+            inter_channel_spectrums: Vec::new(),
+            dual_channel_handler: None,
+            selected_channel: None,
+            layout: Layout::SideBySide,
+        }
+    }
+}
+
+/// `FREQUENCY_TICKS` labels of bins up to `last_bin`
+fn frequency_ticks(last_bin: usize, bin_frequency: impl Fn(usize) -> f32) -> Vec<(f32, String)> {
+    (0..=FREQUENCY_TICKS)
+        .map(|tick| {
+            let bin = (tick * last_bin) / FREQUENCY_TICKS;
+            (
+                tick as f32 / FREQUENCY_TICKS as f32,
+                format!("{:.0} Hz", bin_frequency(bin)),
+            )
+        })
+        .collect()
+}
+
+/// level and phase difference columns mapped into 0..1 with their times and axis labels
+fn inter_channel_data(
+    stereo_handler: &mut StereoHandler,
+) -> Vec<(Vec<Vec<f32>>, Vec<Option<SystemTime>>, Vec<(f32, String)>)> {
+    let ticks = frequency_ticks(stereo_handler.output_size().saturating_sub(1), |bin| {
+        stereo_handler.bin_frequency(bin)
+    });
+    let (mut level_columns, level_times) = split_times(stereo_handler.get_level_difference());
+    let (mut phase_columns, phase_times) = split_times(stereo_handler.get_phase_difference());
+    if let Some(inter_channel) = stereo_handler.inter_channel() {
+        for value in level_columns.iter_mut().flatten() {
+            *value = inter_channel.display_level_difference(*value);
+        }
+        for value in phase_columns.iter_mut().flatten() {
+            *value = inter_channel.display_phase_difference(*value);
+        }
+    }
+    vec![
+        (level_columns, level_times, ticks.clone()),
+        (phase_columns, phase_times, ticks),
+    ]
+}
+
+/// where the shown columns are handed back to be reused
+#[derive(Clone, Copy)]
+enum ColumnSource {
+    Channel(usize),
+    LevelDifference,
+    PhaseDifference,
+}
+
+/// hands inter-channel columns back to `stereo_handler`
+fn recycle_inter_channel(
+    stereo_handler: &mut StereoHandler,
+    source: ColumnSource,
+    columns: Vec<Vec<f32>>,
+) {
+    match source {
+        ColumnSource::LevelDifference => stereo_handler.recycle_level_difference(columns),
+        ColumnSource::PhaseDifference => stereo_handler.recycle_phase_difference(columns),
+        ColumnSource::Channel(_) => (),
+    }
+}
+
+/// transfer function magnitude in dB, phase in degrees and coherence over frequency
+fn transfer_function_plots(ui: &mut Ui, dual_channel_handler: &mut DualChannelHandler) {
+    let (magnitude, phase) = dual_channel_handler.get_transfer_function();
+    let coherence = dual_channel_handler.get_coherence();
+    let frequencies: Vec<f64> = (0..dual_channel_handler.output_size())
+        .map(|bin| dual_channel_handler.bin_frequency(bin) as f64)
+        .collect();
+    let line = |values: Vec<f64>| {
+        Line::new(Values::from_values_iter(
+            frequencies
+                .iter()
+                .zip(values)
+                .map(|(&frequency, value)| Value::new(frequency, value)),
+        ))
+    };
+    let magnitude_db = magnitude
+        .iter()
+        .map(|&magnitude| 20.0 * (magnitude as f64).max(1e-6).log10())
+        .collect();
+    let phase_degrees = phase
+        .iter()
+        .map(|&phase| (phase as f64).to_degrees())
+        .collect();
+    let coherence_values = coherence
+        .iter()
+        .map(|&coherence| coherence as f64)
+        .collect();
+    dual_channel_handler.recycle_columns(vec![magnitude, phase, coherence]);
+
+    let height = (ui.available_height() - 2.0 * ui.spacing().item_spacing.y) / 3.0;
+    Plot::new("transfer_function_magnitude")
+        .legend(Legend::default())
+        .height(height)
+        .show(ui, |plot_ui| {
+            plot_ui.line(line(magnitude_db).name("magnitude in dB"))
+        });
+    Plot::new("transfer_function_phase")
+        .legend(Legend::default())
+        .height(height)
+        .include_y(-180.0)
+        .include_y(180.0)
+        .show(ui, |plot_ui| {
+            plot_ui.line(line(phase_degrees).name("phase in degrees"))
+        });
+    Plot::new("coherence")
+        .legend(Legend::default())
+        .height(height)
+        .include_y(0.0)
+        .include_y(1.0)
+        .show(ui, |plot_ui| {
+            plot_ui.line(line(coherence_values).name("coherence"))
+        });
+}
+
+/// hands columns of `channel_data` back to `stft_handle`
+fn recycle(stft_handle: &mut StftHandler, view: View, columns: Vec<Vec<f32>>) {
+    match view {
+        View::Spectrogram => stft_handle.recycle_spectrum(columns),
+        View::Chromagram => stft_handle.recycle_features(columns),
+    }
+}
+
+/// splits timed columns into columns and their wall times
+fn split_times(timed_columns: Vec<TimedColumn>) -> (Vec<Vec<f32>>, Vec<Option<SystemTime>>) {
+    timed_columns
+        .into_iter()
+        .map(|(timestamp, column)| (column, Some(timestamp.wall_time)))
+        .unzip()
+}
+
+/// splits off columns without `size` values, which were queued before the output size changed.
+/// returns the current columns and the stale ones to be recycled
+fn current_columns(
+    timed_columns: Vec<TimedColumn>,
+    size: usize,
+) -> (Vec<TimedColumn>, Vec<Vec<f32>>) {
+    let (current, stale): (Vec<TimedColumn>, Vec<TimedColumn>) = timed_columns
+        .into_iter()
+        .partition(|(_, column)| column.len() == size);
+    (
+        current,
+        stale.into_iter().map(|(_, column)| column).collect(),
+    )
+}
+
+/// columns with their times and axis labels of the current view of `stft_handle`
+fn channel_data(
+    stft_handle: &mut StftHandler,
+    view: View,
+) -> (Vec<Vec<f32>>, Vec<Option<SystemTime>>, Vec<(f32, String)>) {
+    match view {
+        View::Spectrogram => {
+            // frequency axis is linear for the stft and logarithmic for the cqt
+            let output_size = stft_handle.output_size();
+            let ticks = frequency_ticks(output_size - 1, |bin| stft_handle.bin_frequency(bin));
+            // map scaled values into 0..1 for the texture
+            let scaling = stft_handle.scaling();
+            let (timed_columns, stale_columns) =
+                current_columns(stft_handle.get_spectrum(), output_size);
+            stft_handle.recycle_spectrum(stale_columns);
+            let (mut spectrum_columns, times) = split_times(timed_columns);
+            for spectrum_column in spectrum_columns.iter_mut() {
+                for value in spectrum_column.iter_mut() {
+                    *value = scaling.display_value(*value);
+                }
+            }
+            (spectrum_columns, times, ticks)
+        }
+        View::Chromagram => {
+            // spectrum columns are not shown, but the queue is kept short
+            let (spectrum_columns, _) = split_times(stft_handle.get_spectrum());
+            stft_handle.recycle_spectrum(spectrum_columns);
+            let feature_size = stft_handle.feature_size();
+            let ticks = match stft_handle.chroma_bins() {
+                Some(bins) => (0..bins)
+                    .map(|bin| {
+                        (
+                            (bin as f32 + 0.5) / bins as f32,
+                            pitch_class_name(bin, bins).to_string(),
+                        )
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            // chroma columns are normalized to 0..1
+            let (timed_columns, stale_columns) =
+                current_columns(stft_handle.get_features(), feature_size);
+            stft_handle.recycle_features(stale_columns);
+            let (feature_columns, times) = split_times(timed_columns);
+            (feature_columns, times, ticks)
         }
     }
 }
 
 impl eframe::App for SpectrogramGui {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| {
+            self.controls(ui);
+        });
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            self.status_bar(ui);
+        });
         egui::CentralPanel::default().show(ctx, |ui| {
-            // get data
-            let mut spectrum = Vec::new();
+            // get data, hidden channels are drained as well
+            let mut shown = Vec::new();
+            let stereo_channels = self
+                .stereo_handler
+                .as_ref()
+                .map(|stereo_handler| stereo_handler.handler().channels());
             if let Some(stft_handler) = &mut self.stft_handler {
-                for stft_handle in stft_handler {
-                    stft_handle.run();
-                    spectrum.push(stft_handle.get_spectrum());
+                for (channel, ((stft_handle, settings), channel_spectrum)) in stft_handler
+                    .iter_mut()
+                    .zip(self.settings.iter())
+                    .zip(self.spectrums.iter_mut())
+                    .enumerate()
+                {
+                    let (spectrum_columns, times, ticks) = channel_data(stft_handle, settings.view);
+                    let visible = match &stereo_channels {
+                        Some(stereo_channels) => self.stereo_view.shows(stereo_channels[channel]),
+                        None => true,
+                    };
+                    if visible {
+                        channel_spectrum.ticks = ticks;
+                        channel_spectrum.add_times(times);
+                        shown.push((
+                            channel_spectrum,
+                            spectrum_columns,
+                            ColumnSource::Channel(channel),
+                        ));
+                    } else {
+                        recycle(stft_handle, settings.view, spectrum_columns);
+                    }
+                }
+            };
+            if let Some(stereo_handler) = &self.stereo_handler {
+                let mut stereo_handler = stereo_handler.handler();
+                let inter_channel = inter_channel_data(&mut stereo_handler);
+                let sources = [ColumnSource::LevelDifference, ColumnSource::PhaseDifference];
+                for ((channel_spectrum, (spectrum_columns, times, ticks)), source) in self
+                    .inter_channel_spectrums
+                    .iter_mut()
+                    .zip(inter_channel)
+                    .zip(sources)
+                {
+                    if self.stereo_view == StereoView::InterChannel {
+                        channel_spectrum.ticks = ticks;
+                        channel_spectrum.add_times(times);
+                        shown.push((channel_spectrum, spectrum_columns, source));
+                    } else {
+                        recycle_inter_channel(&mut stereo_handler, source, spectrum_columns);
+                    }
+                }
+            }
+
+            if let Some(dual_channel_handler) = &self.dual_channel_handler {
+                if self.stereo_view == StereoView::TransferFunction {
+                    transfer_function_plots(ui, &mut dual_channel_handler.handler());
+                    ui.ctx().request_repaint();
+                    return;
+                }
+            }
+
+            let channel_count = shown.len().max(1) as f32;
+            let available = ui.available_size();
+            let spacing = ui.spacing().item_spacing;
+            let axis = AXIS_HEIGHT + spacing.y;
+            let time_axis = TIME_AXIS_WIDTH + spacing.x;
+            let (width, height) = match self.layout {
+                Layout::SideBySide => (
+                    (available.x - spacing.x * (channel_count - 1.0)) / channel_count - time_axis,
+                    available.y - axis,
+                ),
+                Layout::Stacked => (
+                    available.x - time_axis,
+                    (available.y - spacing.y * (channel_count - 1.0)) / channel_count - axis,
+                ),
+            };
+            let size = egui::Vec2::new(width.max(16.0), height.max(16.0));
+            // drawn columns are handed back to be reused
+            let mut drawn = Vec::new();
+            let mut show_channels = |ui: &mut Ui| {
+                for (channel_spectrum, spectrum_columns, source) in shown.drain(..) {
+                    ui.vertical(|ui| {
+                        let spectrum_columns = channel_spectrum.ui(ui, spectrum_columns, size);
+                        drawn.push((source, spectrum_columns));
+                    });
                 }
             };
-            self.spectrum.ui(ui, spectrum);
+            match self.layout {
+                Layout::SideBySide => {
+                    ui.horizontal(|ui| show_channels(ui));
+                }
+                Layout::Stacked => {
+                    ui.vertical(|ui| show_channels(ui));
+                }
+            }
+            for (source, spectrum_columns) in drawn {
+                match (source, &mut self.stft_handler, &self.stereo_handler) {
+                    (ColumnSource::Channel(channel), Some(stft_handler), _) => recycle(
+                        &mut stft_handler[channel],
+                        self.settings[channel].view,
+                        spectrum_columns,
+                    ),
+                    (_, _, Some(stereo_handler)) => recycle_inter_channel(
+                        &mut stereo_handler.handler(),
+                        source,
+                        spectrum_columns,
+                    ),
+                    _ => (),
+                }
+            }
         });
     }
 }
@@ -0,0 +1,53 @@
+use spectrogram_lib::stft_handler::StftHandler;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+/// a handler driven by its own thread like `StftHandler::start_worker`,
+/// so the gui thread only reads its results.
+/// the thread is stopped when the worker is dropped
+pub struct Worker<H> {
+    handler: Arc<Mutex<H>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<H: Send + 'static> Worker<H> {
+    /// calls `run` on `handler` whenever its input may hold new audio
+    pub fn start(handler: H, run: fn(&mut H)) -> Self {
+        let handler = Arc::new(Mutex::new(handler));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let handler = handler.clone();
+            let running = running.clone();
+            thread::spawn(move || {
+                while running.load(Ordering::Acquire) {
+                    run(&mut handler.lock().expect("Unlock"));
+                    thread::park_timeout(StftHandler::WORKER_INTERVAL);
+                }
+            })
+        };
+        Self {
+            handler,
+            running,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<H> Worker<H> {
+    /// locks the handler, waits while the thread runs it
+    pub fn handler(&self) -> MutexGuard<'_, H> {
+        self.handler.lock().expect("Unlock")
+    }
+}
+
+impl<H> Drop for Worker<H> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
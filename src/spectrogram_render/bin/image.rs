@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+pub const BLACK: [u8; 3] = [0, 0, 0];
+pub const WHITE: [u8; 3] = [255, 255, 255];
+
+/// glyphs are 3 x 5 pixels, drawn `FONT_SCALE` times larger
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const FONT_SCALE: usize = 2;
+/// horizontal distance of two characters in pixels
+const ADVANCE: usize = (GLYPH_WIDTH + 1) * FONT_SCALE;
+pub const TEXT_HEIGHT: usize = GLYPH_HEIGHT * FONT_SCALE;
+
+/// rows of a glyph from top to bottom, bit 2 is the left pixel.
+/// covers the characters of the axis labels, others are left blank
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT] {
+    match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'k' => [0b100, 0b101, 0b110, 0b101, 0b101],
+        'z' => [0b000, 0b111, 0b001, 0b010, 0b111],
+        's' => [0b000, 0b011, 0b110, 0b001, 0b110],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+/// horizontal placement of a text relative to its x coordinate
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Align {
+    Center,
+    Right,
+}
+
+/// rgb image with 8 bits per channel
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, background: [u8; 3]) -> Self {
+        Self {
+            width,
+            height,
+            pixels: background.repeat(width * height),
+        }
+    }
+
+    /// pixels outside of the image are ignored
+    pub fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let index = 3 * (y * self.width + x);
+            self.pixels[index..index + 3].copy_from_slice(&color);
+        }
+    }
+
+    pub fn horizontal_line(&mut self, x_start: usize, x_end: usize, y: usize, color: [u8; 3]) {
+        for x in x_start..=x_end {
+            self.set(x, y, color);
+        }
+    }
+
+    pub fn vertical_line(&mut self, x: usize, y_start: usize, y_end: usize, color: [u8; 3]) {
+        for y in y_start..=y_end {
+            self.set(x, y, color);
+        }
+    }
+
+    pub fn text_width(text: &str) -> usize {
+        (text.chars().count() * ADVANCE).saturating_sub(FONT_SCALE)
+    }
+
+    /// draws `text` with its top at `y`
+    pub fn text(&mut self, x: usize, y: usize, align: Align, text: &str, color: [u8; 3]) {
+        let width = Self::text_width(text);
+        let left = match align {
+            Align::Center => x.saturating_sub(width / 2),
+            Align::Right => x.saturating_sub(width),
+        };
+        for (index, character) in text.chars().enumerate() {
+            for (row, bits) in glyph(character).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+                    for dy in 0..FONT_SCALE {
+                        for dx in 0..FONT_SCALE {
+                            self.set(
+                                left + index * ADVANCE + column * FONT_SCALE + dx,
+                                y + row * FONT_SCALE + dy,
+                                color,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), &'static str> {
+        let file = File::create(path).map_err(|_| "cannot create png file")?;
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|_| "cannot write png header")?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(|_| "cannot write png data")
+    }
+}
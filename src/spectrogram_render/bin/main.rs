@@ -0,0 +1,229 @@
+mod image;
+use image::{Align, Image, BLACK, TEXT_HEIGHT, WHITE};
+use ringbuf::HeapRb;
+use spectrogram_lib::audio_file::AudioFile;
+use spectrogram_lib::colormap::Colormap;
+use spectrogram_lib::fifo_queue::OverflowPolicy;
+use spectrogram_lib::file_source::{FileSource, Pacing};
+use spectrogram_lib::stft::{calculate_stft_with_scaling, ScalingMode};
+use spectrogram_lib::stft_handler::{StftConfig, StftHandler};
+use std::process::exit;
+use std::thread;
+use std::time::Duration;
+
+const USAGE: &str = "usage: spectrogram_render <input.wav | input.flac> <output.png> \
+[--window <samples>] [--hop <samples>] [--scale <scaling>] \
+[--colormap <gray | heat | viridis>] [--channel <channel>] [--pacing <fast | realtime>]";
+
+const DEFAULT_WINDOW_SIZE: usize = 1024;
+const DEFAULT_HOP_SIZE: usize = 256;
+/// number of labels on the frequency axis, like the gui
+const FREQUENCY_TICKS: usize = 4;
+/// number of labels on the time axis
+const TIME_TICKS: usize = 5;
+/// space around the spectrogram in pixels, left and bottom hold the axes
+const MARGIN_LEFT: usize = 70;
+const MARGIN_RIGHT: usize = 30;
+const MARGIN_TOP: usize = 10;
+const MARGIN_BOTTOM: usize = 30;
+const TICK_LENGTH: usize = 4;
+/// minimal capacity of the ringbuffer of `--pacing` in samples
+const RINGBUFFER_SIZE: usize = 1 << 16;
+/// time between two passes of the `StftHandler` with `--pacing`
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+struct Options {
+    input: String,
+    output: String,
+    window_size: usize,
+    hop_size: usize,
+    scaling: ScalingMode,
+    colormap: Colormap,
+    /// mean of all channels if not set
+    channel: Option<usize>,
+    /// plays the file through a `FileSource` into a `StftHandler` like a live input if set,
+    /// else the whole file is analyzed at once
+    pacing: Option<Pacing>,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, &'static str> {
+        let mut args = std::env::args().skip(1);
+        let input = args.next().ok_or("missing input file")?;
+        let output = args.next().ok_or("missing output file")?;
+        let mut options = Options {
+            input,
+            output,
+            window_size: DEFAULT_WINDOW_SIZE,
+            hop_size: DEFAULT_HOP_SIZE,
+            scaling: ScalingMode::default(),
+            colormap: Colormap::default(),
+            channel: None,
+            pacing: None,
+        };
+        while let Some(option) = args.next() {
+            let value = args.next().ok_or("missing option value")?;
+            match &option[..] {
+                "--window" => {
+                    options.window_size = value.parse().map_err(|_| "invalid window size")?
+                }
+                "--hop" => options.hop_size = value.parse().map_err(|_| "invalid hop size")?,
+                "--scale" => options.scaling = value.parse()?,
+                "--colormap" => options.colormap = value.parse()?,
+                "--channel" => {
+                    options.channel = Some(value.parse().map_err(|_| "invalid channel")?)
+                }
+                "--pacing" => options.pacing = Some(value.parse()?),
+                _ => return Err("unknown option"),
+            }
+        }
+        if options.window_size < 2 {
+            return Err("window size has to be at least 2");
+        }
+        if options.hop_size == 0 || options.hop_size > options.window_size {
+            return Err("hop size has to be in 1..=window size");
+        }
+        Ok(options)
+    }
+}
+
+/// spectrum columns of `samples` computed by a `StftHandler`, fed by a `FileSource` with `pacing`.
+/// runs the handler until the source played the whole file
+fn stream_columns(
+    samples: Vec<f32>,
+    sample_rate: u32,
+    options: &Options,
+    pacing: Pacing,
+) -> Result<Vec<Vec<f32>>, &'static str> {
+    let config = StftConfig::default()
+        .with_window_size(options.window_size)
+        .with_fft_size(options.window_size)
+        .with_step_size(options.hop_size)
+        .with_scaling(options.scaling);
+    config.validate()?;
+    let file = AudioFile::new(sample_rate, 1, samples)?;
+    let (ringbuffer_in, ringbuffer_out) =
+        HeapRb::<f32>::new(RINGBUFFER_SIZE.max(options.window_size)).split();
+    let mut stft_handler = StftHandler::new_with_config(ringbuffer_out, config);
+    stft_handler.set_sample_rate(sample_rate as f32)?;
+    // the handler is drained in time, full queues only wait
+    stft_handler.set_overflow_policy(OverflowPolicy::Block(OverflowPolicy::DEFAULT_TIMEOUT));
+    let source = FileSource::start(
+        file,
+        vec![ringbuffer_in],
+        vec![stft_handler.stream_stats()],
+        pacing,
+    );
+    let mut columns = Vec::new();
+    loop {
+        let finished = source.is_finished();
+        stft_handler.run();
+        let timed_columns = stft_handler.get_spectrum();
+        if finished && timed_columns.is_empty() {
+            break;
+        }
+        columns.extend(timed_columns.into_iter().map(|(_, column)| column));
+        if !finished {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    if stft_handler.dropped_samples() + stft_handler.dropped_columns() > 0 {
+        eprintln!(
+            "lost {} samples and {} columns",
+            stft_handler.dropped_samples(),
+            stft_handler.dropped_columns()
+        );
+    }
+    Ok(columns)
+}
+
+/// renders the spectrogram of `options.input` with time from left to right
+/// and frequency from bottom to top, returns the image and the number of columns
+fn render(options: &Options) -> Result<(Image, usize), &'static str> {
+    let file = AudioFile::open(&options.input)?;
+    let samples = match options.channel {
+        Some(channel) if channel < file.channel_count => file.channel(channel),
+        Some(_) => return Err("no such channel"),
+        None => file.mono(),
+    };
+    if samples.len() < options.window_size {
+        return Err("file is shorter than a window");
+    }
+    let columns = match options.pacing {
+        Some(pacing) => stream_columns(samples, file.sample_rate, options, pacing)?,
+        None => calculate_stft_with_scaling(
+            &samples,
+            options.window_size,
+            options.hop_size,
+            options.scaling,
+        ),
+    };
+    let width = columns.len();
+    let height = columns[0].len();
+    let mut image = Image::new(
+        MARGIN_LEFT + width + MARGIN_RIGHT,
+        MARGIN_TOP + height + MARGIN_BOTTOM,
+        WHITE,
+    );
+    for (x, column) in columns.iter().enumerate() {
+        for (bin, value) in column.iter().enumerate() {
+            let color = options
+                .colormap
+                .value_rgb(options.scaling.display_value(*value));
+            image.set(MARGIN_LEFT + x, MARGIN_TOP + height - 1 - bin, color);
+        }
+    }
+
+    let sample_rate = file.sample_rate as f32;
+    let left = MARGIN_LEFT - 1;
+    let bottom = MARGIN_TOP + height;
+    image.vertical_line(left, MARGIN_TOP, bottom, BLACK);
+    image.horizontal_line(left, MARGIN_LEFT + width, bottom, BLACK);
+    for tick in 0..=FREQUENCY_TICKS {
+        let bin = (tick * (height - 1)) / FREQUENCY_TICKS;
+        let y = bottom - 1 - bin;
+        let frequency = bin as f32 * sample_rate / options.window_size as f32;
+        image.horizontal_line(left - TICK_LENGTH, left, y, BLACK);
+        image.text(
+            left - 2 * TICK_LENGTH,
+            y.saturating_sub(TEXT_HEIGHT / 2),
+            Align::Right,
+            &format!("{:.0} Hz", frequency),
+            BLACK,
+        );
+    }
+    for tick in 0..TIME_TICKS {
+        let column = (tick * (width - 1)) / (TIME_TICKS - 1);
+        let x = MARGIN_LEFT + column;
+        let time = (column * options.hop_size) as f32 / sample_rate;
+        image.vertical_line(x, bottom, bottom + TICK_LENGTH, BLACK);
+        image.text(
+            x,
+            bottom + 2 * TICK_LENGTH,
+            Align::Center,
+            &format!("{:.1} s", time),
+            BLACK,
+        );
+    }
+    Ok((image, width))
+}
+
+fn main() {
+    let options = Options::from_args().unwrap_or_else(|error| {
+        eprintln!("{}\n{}", error, USAGE);
+        exit(-1)
+    });
+    let (image, column_count) = render(&options)
+        .and_then(|(image, column_count)| {
+            image.save_png(&options.output)?;
+            Ok((image, column_count))
+        })
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            exit(-1)
+        });
+    println!(
+        "wrote {}: {} columns, {} x {} pixels",
+        options.output, column_count, image.width, image.height
+    );
+}
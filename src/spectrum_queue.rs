@@ -1,27 +1,87 @@
-use crate::fifo_queue::FifoQueue;
+use crate::fifo_queue::{FifoQueue, OverflowPolicy};
 use std::collections::VecDeque;
 
-pub struct SpectrumQueue {
-    pub data: VecDeque<Vec<f32>>,
+/// queue of spectrum columns, magnitude columns by default
+///
+/// columns dropped by `push` and columns handed back with `recycle`
+/// are kept to be reused by the producer with `take_recycled`.
+/// `OverflowPolicy::Block` cannot wait while the caller holds the queue's lock,
+/// it drops the new column like `OverflowPolicy::DropNewest`
+pub struct SpectrumQueue<T = Vec<f32>> {
+    pub data: VecDeque<T>,
     pub size: usize,
+    /// unused columns, at most `size`
+    pub recycled: Vec<T>,
+    /// number of columns dropped by `push` because the queue was full
+    pub dropped: usize,
+    pub policy: OverflowPolicy,
+    /// number of columns pushed into the full queue, for `OverflowPolicy::Decimate`
+    overflow_count: usize,
 }
 
-impl FifoQueue<Vec<f32>> for SpectrumQueue {
-    fn new(size: usize) -> Self {
+impl<T> SpectrumQueue<T> {
+    pub fn new(size: usize) -> Self {
+        Self::new_with_policy(size, OverflowPolicy::default())
+    }
+
+    /// # Panics
+    /// panics on `OverflowPolicy::Decimate(0)`
+    pub fn new_with_policy(size: usize, policy: OverflowPolicy) -> Self {
+        assert!(policy != OverflowPolicy::Decimate(0));
         SpectrumQueue {
             data: VecDeque::new(),
             size,
+            recycled: Vec::new(),
+            dropped: 0,
+            policy,
+            overflow_count: 0,
         }
     }
 
-    fn push(&mut self, new_data: Vec<f32>) {
+    fn drop_new(&mut self, new_data: T) {
+        self.dropped += 1;
+        self.recycle(new_data);
+    }
+
+    /// keeps `item` for reuse, it is dropped if `size` items are kept already
+    pub fn recycle(&mut self, item: T) {
+        if self.recycled.len() < self.size {
+            self.recycled.push(item);
+        }
+    }
+
+    /// an unused column, its content is stale
+    pub fn take_recycled(&mut self) -> Option<T> {
+        self.recycled.pop()
+    }
+}
+
+impl<T> FifoQueue<T> for SpectrumQueue<T> {
+    fn push(&mut self, new_data: T) {
+        let full = self.data.len() >= self.size;
+        match self.policy {
+            OverflowPolicy::DropOldest => (),
+            OverflowPolicy::DropNewest | OverflowPolicy::Block(_) if full => {
+                return self.drop_new(new_data);
+            }
+            OverflowPolicy::Decimate(decimation) if full => {
+                self.overflow_count += 1;
+                if !self.overflow_count.is_multiple_of(decimation) {
+                    return self.drop_new(new_data);
+                }
+            }
+            _ => self.overflow_count = 0,
+        }
         self.data.push_back(new_data);
         while self.data.len() > self.size {
-            self.data.pop_front();
+            if let Some(old_data) = self.data.pop_front() {
+                self.dropped += 1;
+                self.recycle(old_data);
+            }
         }
     }
 
-    fn pop(&mut self) -> Option<Vec<f32>> {
+    fn pop(&mut self) -> Option<T> {
         self.data.pop_front()
     }
 
@@ -0,0 +1,405 @@
+use crate::fifo_queue::{FifoConsumer, FifoProducer, OverflowPolicy};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// time a producer with `OverflowPolicy::Block` sleeps between checks for space
+const BLOCK_INTERVAL: Duration = Duration::from_millis(1);
+
+/// slot of a `Ring`, `sequence` tells whether it may be pushed into or popped from
+struct Slot<T> {
+    sequence: AtomicUsize,
+    frame: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// lock-free bounded ring after Dmitry Vyukov's queue.
+/// one thread pushes, the consumer and the producer pop,
+/// the producer to drop the oldest frame of a full ring
+struct Ring<T> {
+    slots: Box<[Slot<T>]>,
+    /// index of the next frame to pop
+    head: AtomicUsize,
+    /// index of the next frame to push
+    tail: AtomicUsize,
+}
+
+// frames are only accessed by the thread which claimed their slot
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity)
+                .map(|index| Slot {
+                    sequence: AtomicUsize::new(index),
+                    frame: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// only called by the producer. fails if the ring is full,
+    /// or while the slot of the oldest frame is still being popped
+    fn push(&self, frame: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let slot = &self.slots[tail % self.slots.len()];
+        if slot.sequence.load(Ordering::Acquire) != tail {
+            return Err(frame);
+        }
+        // SAFETY: the sequence shows the slot is empty and only the producer pushes
+        unsafe { (*slot.frame.get()).write(frame) };
+        slot.sequence.store(tail.wrapping_add(1), Ordering::Release);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// oldest frame, called by the consumer and the producer
+    fn pop(&self) -> Option<T> {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[head % self.slots.len()];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let ahead = sequence.wrapping_sub(head.wrapping_add(1)) as isize;
+            if ahead < 0 {
+                return None;
+            }
+            if ahead > 0 {
+                // popped by the other side meanwhile
+                head = self.head.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // SAFETY: the won exchange of `head` makes this the only reader of the slot,
+                    // the sequence shows the frame was written
+                    let frame = unsafe { (*slot.frame.get()).assume_init_read() };
+                    slot.sequence
+                        .store(head.wrapping_add(self.slots.len()), Ordering::Release);
+                    return Some(frame);
+                }
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(self.slots.len())
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// lock-free single producer single consumer queue of frames, e.g. spectrum columns,
+/// neither side takes a lock, only `OverflowPolicy::Block` lets the producer wait for space.
+///
+/// with the default `OverflowPolicy::DropOldest` it keeps the newest `size` frames
+/// like `SpectrumQueue`: older frames are dropped when the consumer pops,
+/// or by the producer if the consumer did not pop for another `size` frames.
+/// the other policies are applied by the producer, see `SpscProducer::set_policy`.
+/// frames handed back with `recycle` flow back to the producer through a second queue
+/// # Panics
+/// panics if `size` is 0
+pub fn spsc_queue<T>(size: usize) -> (SpscProducer<T>, SpscConsumer<T>) {
+    assert!(size > 0);
+    let queue = Arc::new(Ring::new(2 * size));
+    let (recycled_in, recycled_out) = HeapRb::new(size).split();
+    let dropped = Arc::new(AtomicUsize::new(0));
+    (
+        SpscProducer {
+            queue: queue.clone(),
+            recycled: recycled_out,
+            spare: None,
+            size,
+            policy: OverflowPolicy::default(),
+            overflow_count: 0,
+            blocked_since: None,
+            dropped: dropped.clone(),
+        },
+        SpscConsumer {
+            queue,
+            recycled: recycled_in,
+            size,
+            dropped,
+        },
+    )
+}
+
+pub struct SpscProducer<T> {
+    queue: Arc<Ring<T>>,
+    recycled: HeapConsumer<T>,
+    /// last frame dropped by the producer, reused before the recycled frames
+    spare: Option<T>,
+    size: usize,
+    policy: OverflowPolicy,
+    /// number of frames pushed into the full queue, for `OverflowPolicy::Decimate`
+    overflow_count: usize,
+    /// start of the wait for space of the next frame, for `OverflowPolicy::Block`
+    blocked_since: Option<Instant>,
+    dropped: Arc<AtomicUsize>,
+}
+
+pub struct SpscConsumer<T> {
+    queue: Arc<Ring<T>>,
+    recycled: HeapProducer<T>,
+    size: usize,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl<T> SpscProducer<T> {
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// `OverflowPolicy::Block` makes `push` wait for the consumer,
+    /// use it only if the consumer runs in another thread
+    /// # Panics
+    /// panics on `OverflowPolicy::Decimate(0)`
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        assert!(policy != OverflowPolicy::Decimate(0));
+        self.policy = policy;
+        self.overflow_count = 0;
+        self.blocked_since = None;
+    }
+
+    /// true once the consumer was dropped
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.dropped) == 1
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.len() >= self.size
+    }
+
+    /// true while the queue is full and `OverflowPolicy::Block` still waits for the consumer.
+    /// lets the caller wait without holding its locks, the time waited counts towards
+    /// the timeout of the next `push`
+    pub fn is_blocked(&mut self) -> bool {
+        match self.policy {
+            OverflowPolicy::Block(timeout) if self.is_full() => {
+                self.blocked_since
+                    .get_or_insert_with(Instant::now)
+                    .elapsed()
+                    < timeout
+            }
+            _ => false,
+        }
+    }
+
+    /// waits until the consumer made space or `timeout` passed
+    fn wait_for_space(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.is_full() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            std::thread::sleep(BLOCK_INTERVAL.min(deadline - now));
+        }
+        true
+    }
+
+    /// drops the oldest frame and reuses it for the next push
+    fn drop_oldest(&mut self) {
+        if let Some(old_data) = self.queue.pop() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            self.spare = Some(old_data);
+        }
+    }
+
+    fn drop_new(&mut self, new_data: T) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.spare = Some(new_data);
+    }
+}
+
+impl<T> FifoProducer<T> for SpscProducer<T> {
+    fn push(&mut self, new_data: T) {
+        let full = self.is_full();
+        match self.policy {
+            OverflowPolicy::DropOldest => (),
+            OverflowPolicy::DropNewest if full => return self.drop_new(new_data),
+            OverflowPolicy::Block(timeout) if full => {
+                let waited = self
+                    .blocked_since
+                    .take()
+                    .map_or(Duration::ZERO, |since| since.elapsed());
+                if !self.wait_for_space(timeout.saturating_sub(waited)) {
+                    return self.drop_new(new_data);
+                }
+            }
+            OverflowPolicy::Decimate(decimation) if full => {
+                self.overflow_count += 1;
+                if !self.overflow_count.is_multiple_of(decimation) {
+                    return self.drop_new(new_data);
+                }
+            }
+            _ => {
+                self.overflow_count = 0;
+                self.blocked_since = None;
+            }
+        }
+        let new_data = match self.queue.push(new_data) {
+            Err(new_data)
+                if matches!(
+                    self.policy,
+                    OverflowPolicy::DropOldest | OverflowPolicy::Decimate(_)
+                ) =>
+            {
+                self.drop_oldest();
+                self.queue.push(new_data)
+            }
+            result => result,
+        };
+        if let Err(new_data) = new_data {
+            self.drop_new(new_data);
+        }
+    }
+
+    fn take_recycled(&mut self) -> Option<T> {
+        self.spare.take().or_else(|| self.recycled.pop())
+    }
+}
+
+impl<T> FifoConsumer<T> for SpscConsumer<T> {
+    fn pop(&mut self) -> Option<T> {
+        // frames older than the newest `size` are dropped
+        while self.queue.len() > self.size {
+            if let Some(old_data) = self.queue.pop() {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                self.recycle(old_data);
+            }
+        }
+        self.queue.pop()
+    }
+
+    fn recycle(&mut self, frame: T) {
+        // dropped if the producer has enough frames to reuse
+        let _ = self.recycled.push(frame);
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len().min(self.size)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.len() == 0
+    }
+
+    /// includes the frames beyond `size` which are dropped on the next `pop`
+    fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed) + self.queue.len().saturating_sub(self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_oldest_keeps_newest_frames() {
+        let (mut producer, mut consumer) = spsc_queue(4);
+        for frame in 0..20 {
+            producer.push(frame);
+        }
+        let mut frames = Vec::new();
+        while let Some(frame) = consumer.pop() {
+            frames.push(frame);
+        }
+        assert_eq!(frames, vec![16, 17, 18, 19]);
+        assert_eq!(consumer.dropped(), 16);
+    }
+
+    #[test]
+    fn drop_newest_keeps_oldest_frames() {
+        let (mut producer, mut consumer) = spsc_queue(2);
+        producer.set_policy(OverflowPolicy::DropNewest);
+        for frame in 0..5 {
+            producer.push(frame);
+        }
+        // the last dropped frame is reused first
+        assert_eq!(producer.take_recycled(), Some(4));
+        assert_eq!(consumer.pop(), Some(0));
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(consumer.pop(), None);
+        assert_eq!(consumer.dropped(), 3);
+    }
+
+    #[test]
+    fn block_waits_for_the_consumer_until_the_timeout() {
+        let timeout = Duration::from_millis(50);
+        let (mut producer, mut consumer) = spsc_queue(1);
+        producer.set_policy(OverflowPolicy::Block(timeout));
+        producer.push(0);
+
+        let start = Instant::now();
+        producer.push(1);
+        assert!(start.elapsed() >= timeout);
+        assert_eq!(consumer.dropped(), 1);
+
+        // the time waited in is_blocked counts towards the timeout of the next push
+        assert!(producer.is_blocked());
+        std::thread::sleep(timeout);
+        assert!(!producer.is_blocked());
+        let start = Instant::now();
+        producer.push(2);
+        assert!(start.elapsed() < timeout);
+        assert_eq!(consumer.dropped(), 2);
+
+        // a pop of the consumer lets the waiting push through
+        let consumer_thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            let first = consumer.pop();
+            while consumer.is_empty() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            (first, consumer.pop(), consumer.dropped())
+        });
+        producer.set_policy(OverflowPolicy::Block(Duration::from_secs(10)));
+        producer.push(3);
+        assert_eq!(consumer_thread.join().unwrap(), (Some(0), Some(3), 2));
+    }
+
+    #[test]
+    fn decimate_keeps_every_nth_frame_of_a_full_queue() {
+        let (mut producer, mut consumer) = spsc_queue(2);
+        producer.set_policy(OverflowPolicy::Decimate(3));
+        for frame in 0..6 {
+            producer.push(frame);
+        }
+        let mut frames = Vec::new();
+        while let Some(frame) = consumer.pop() {
+            frames.push(frame);
+        }
+        // 2, 3 and 5 are decimated, 0 is dropped to keep the newest 2 frames
+        assert_eq!(frames, vec![1, 4]);
+        assert_eq!(consumer.dropped(), 4);
+
+        // the count starts over once the queue had space again
+        for frame in 10..15 {
+            producer.push(frame);
+        }
+        let mut frames = Vec::new();
+        while let Some(frame) = consumer.pop() {
+            frames.push(frame);
+        }
+        assert_eq!(frames, vec![11, 14]);
+        assert_eq!(consumer.dropped(), 7);
+    }
+}
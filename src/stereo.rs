@@ -0,0 +1,162 @@
+use crate::stft::FromF64;
+use num::complex::Complex;
+use std::str::FromStr;
+
+/// channel derived from a left and a right input
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum DerivedChannel {
+    #[default]
+    Left,
+    Right,
+    /// `(L + R) / 2`, a centered signal keeps its level
+    Mid,
+    /// `(L - R) / 2`, zero for a centered signal
+    Side,
+}
+
+impl FromStr for DerivedChannel {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        match &lower[..] {
+            "left" | "l" => Ok(DerivedChannel::Left),
+            "right" | "r" => Ok(DerivedChannel::Right),
+            "mid" | "m" => Ok(DerivedChannel::Mid),
+            "side" | "s" => Ok(DerivedChannel::Side),
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for DerivedChannel {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+impl DerivedChannel {
+    pub fn values() -> [DerivedChannel; 4] {
+        static DERIVED_CHANNELS: [DerivedChannel; 4] = [
+            DerivedChannel::Left,
+            DerivedChannel::Right,
+            DerivedChannel::Mid,
+            DerivedChannel::Side,
+        ];
+        DERIVED_CHANNELS
+    }
+
+    /// sample of this channel from a `left` and a `right` sample
+    #[inline]
+    pub fn derive<T: num::Float + FromF64>(&self, left: T, right: T) -> T {
+        let half: T = FromF64::from_f64(0.5);
+        match self {
+            DerivedChannel::Left => left,
+            DerivedChannel::Right => right,
+            DerivedChannel::Mid => (left + right) * half,
+            DerivedChannel::Side => (left - right) * half,
+        }
+    }
+}
+
+/// easy to use function to derive a channel of a stereo signal
+pub fn calculate_derived_channel(left: &[f32], right: &[f32], channel: DerivedChannel) -> Vec<f32> {
+    left.iter()
+        .zip(right.iter())
+        .map(|(&left, &right)| channel.derive(left, right))
+        .collect()
+}
+
+/// per bin inter-channel level difference (ILD) and phase difference (IPD)
+/// of two complex stft columns, e.g. of `STFT::compute_into_complex_output`
+///
+/// the level difference is `20 * log10(|L| / |R|)` in dB, positive if left is louder,
+/// clamped to `±max_level_difference`.
+/// the phase difference is `arg(L * conj(R))` in radians in `-π..=π`.
+/// bins where both channels are below `threshold` are set to 0
+pub struct InterChannel<T> {
+    /// level difference in dB mapped to the ends of the display range
+    pub max_level_difference: T,
+    /// linear magnitude below which a bin is treated as silent
+    pub threshold: T,
+}
+
+impl<T> Default for InterChannel<T>
+where
+    T: num::Float + FromF64,
+{
+    fn default() -> Self {
+        Self::new(
+            FromF64::from_f64(Self::DEFAULT_MAX_LEVEL_DIFFERENCE),
+            FromF64::from_f64(Self::DEFAULT_THRESHOLD),
+        )
+    }
+}
+
+impl<T> InterChannel<T>
+where
+    T: num::Float + FromF64,
+{
+    pub const DEFAULT_MAX_LEVEL_DIFFERENCE: f64 = 24.0;
+    pub const DEFAULT_THRESHOLD: f64 = 1e-6;
+
+    pub fn new(max_level_difference: T, threshold: T) -> Self {
+        assert!(max_level_difference > T::zero());
+        assert!(threshold >= T::zero());
+        InterChannel {
+            max_level_difference,
+            threshold,
+        }
+    }
+
+    /// # Panics
+    /// panics unless `level_difference` and `phase_difference` have the same length
+    /// and `left` and `right` have at least that many bins
+    pub fn compute(
+        &self,
+        left: &[Complex<T>],
+        right: &[Complex<T>],
+        level_difference: &mut [T],
+        phase_difference: &mut [T],
+    ) {
+        assert_eq!(level_difference.len(), phase_difference.len());
+        assert!(left.len() >= level_difference.len());
+        assert!(right.len() >= level_difference.len());
+
+        let twenty: T = FromF64::from_f64(20.0);
+        // keeps the ratio finite if only one channel is silent
+        let floor = self.threshold.max(T::min_positive_value());
+        for (((left, right), level), phase) in left
+            .iter()
+            .zip(right.iter())
+            .zip(level_difference.iter_mut())
+            .zip(phase_difference.iter_mut())
+        {
+            let (left_norm, right_norm) = (left.norm(), right.norm());
+            if left_norm < self.threshold && right_norm < self.threshold {
+                *level = T::zero();
+                *phase = T::zero();
+                continue;
+            }
+            *level = (twenty * (left_norm.max(floor) / right_norm.max(floor)).log10())
+                .max(-self.max_level_difference)
+                .min(self.max_level_difference);
+            *phase = (left * right.conj()).arg();
+        }
+    }
+
+    /// maps a level difference into `0..=1`, 0.5 is equal level
+    pub fn display_level_difference(&self, level_difference: T) -> T {
+        let half: T = FromF64::from_f64(0.5);
+        let value = half + half * level_difference / self.max_level_difference;
+        value.max(T::zero()).min(T::one())
+    }
+
+    /// maps a phase difference into `0..=1`, 0.5 is in phase
+    pub fn display_phase_difference(&self, phase_difference: T) -> T {
+        let half: T = FromF64::from_f64(0.5);
+        let pi: T = FromF64::from_f64(std::f64::consts::PI);
+        let value = half + half * phase_difference / pi;
+        value.max(T::zero()).min(T::one())
+    }
+}
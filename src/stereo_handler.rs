@@ -0,0 +1,286 @@
+use crate::fifo_queue::{FifoConsumer, FifoProducer};
+use crate::spsc_queue::{spsc_queue, SpscConsumer, SpscProducer};
+use crate::stereo::{DerivedChannel, InterChannel};
+use crate::stft::WindowType;
+use crate::stft::STFT;
+use crate::stft_handler::{
+    column_timestamp, copy_window, recycled_column, ConsumerRbf32, ProducerRbf32, TimedColumn,
+};
+use crate::stream_stats::{StreamStats, StreamTime, Timestamp};
+use num::complex::Complex;
+use ringbuf::HeapRb;
+use std::sync::Arc;
+
+/// number of samples taken from each input per pass
+const BLOCK_SIZE: usize = 1024;
+
+/// number of inter-channel columns kept for the consumer
+const QUEUE_SIZE: usize = 2048;
+
+/// left and right windows and stfts of the inter-channel analysis
+struct InterChannelAnalysis {
+    inter_channel: InterChannel<f32>,
+    left_stft: STFT<f32>,
+    right_stft: STFT<f32>,
+    /// samples not analyzed yet, a window is kept until `step_size` samples follow it
+    left_samples_in: ProducerRbf32,
+    left_samples_out: ConsumerRbf32,
+    right_samples_in: ProducerRbf32,
+    right_samples_out: ConsumerRbf32,
+    left_window: Vec<f32>,
+    right_window: Vec<f32>,
+    left_complex: Vec<Complex<f32>>,
+    right_complex: Vec<Complex<f32>>,
+    /// index of the first sample of the next window in the input stream
+    sample_index: u64,
+}
+
+/// reads a left and a right ringbuffer sample synchronously and
+///
+/// * feeds derived channels (left, right, mid, side) into ringbuffers,
+///   one `StftHandler` per `add_channel` consumer shows their spectrogram
+/// * computes inter-channel level and phase difference columns,
+///   read with `get_level_difference` and `get_phase_difference`
+pub struct StereoHandler {
+    left_out: ConsumerRbf32,
+    right_out: ConsumerRbf32,
+    outputs: Vec<(DerivedChannel, ProducerRbf32)>,
+    left_block: Vec<f32>,
+    right_block: Vec<f32>,
+    analysis: Option<InterChannelAnalysis>,
+    level_in: SpscProducer<TimedColumn>,
+    level_out: SpscConsumer<TimedColumn>,
+    phase_in: SpscProducer<TimedColumn>,
+    phase_out: SpscConsumer<TimedColumn>,
+    /// number of samples read from each input so far
+    sample_index: u64,
+    sample_rate: f32,
+    /// samples dropped by full output ringbuffers are counted here
+    stream_stats: Arc<StreamStats>,
+}
+
+impl StereoHandler {
+    pub fn new(left_out: ConsumerRbf32, right_out: ConsumerRbf32) -> Self {
+        let (level_in, level_out) = spsc_queue(QUEUE_SIZE);
+        let (phase_in, phase_out) = spsc_queue(QUEUE_SIZE);
+        Self {
+            left_out,
+            right_out,
+            outputs: Vec::new(),
+            left_block: vec![0.0; BLOCK_SIZE],
+            right_block: vec![0.0; BLOCK_SIZE],
+            analysis: None,
+            level_in,
+            level_out,
+            phase_in,
+            phase_out,
+            sample_index: 0,
+            sample_rate: 48000.0,
+            stream_stats: Arc::new(StreamStats::new()),
+        }
+    }
+
+    /// adds a derived channel, returns the consumer of its ringbuffer
+    /// with `capacity` samples, e.g. for `StftHandler::new`
+    pub fn add_channel(&mut self, channel: DerivedChannel, capacity: usize) -> ConsumerRbf32 {
+        let (producer, consumer) = HeapRb::<f32>::new(capacity).split();
+        self.outputs.push((channel, producer));
+        consumer
+    }
+
+    /// derived channels in the order they were added
+    pub fn channels(&self) -> Vec<DerivedChannel> {
+        self.outputs.iter().map(|(channel, _)| *channel).collect()
+    }
+
+    /// enables the inter-channel analysis with the same window and sizes as an `STFT`,
+    /// `None` disables it
+    pub fn set_inter_channel(
+        &mut self,
+        inter_channel: Option<InterChannel<f32>>,
+        window_type: WindowType,
+        window_size: usize,
+        fft_size: usize,
+        step_size: usize,
+    ) {
+        self.analysis = inter_channel.map(|inter_channel| {
+            let left_stft = STFT::new_with_fft_size(window_type, window_size, fft_size, step_size);
+            let right_stft = STFT::new_with_fft_size(window_type, window_size, fft_size, step_size);
+            let complex_output_size = left_stft.complex_output_size();
+            let (left_samples_in, left_samples_out) =
+                HeapRb::<f32>::new(window_size + BLOCK_SIZE).split();
+            let (right_samples_in, right_samples_out) =
+                HeapRb::<f32>::new(window_size + BLOCK_SIZE).split();
+            InterChannelAnalysis {
+                inter_channel,
+                left_stft,
+                right_stft,
+                left_samples_in,
+                left_samples_out,
+                right_samples_in,
+                right_samples_out,
+                left_window: vec![0.0; window_size],
+                right_window: vec![0.0; window_size],
+                left_complex: vec![Complex::new(0.0, 0.0); complex_output_size],
+                right_complex: vec![Complex::new(0.0, 0.0); complex_output_size],
+                sample_index: self.sample_index,
+            }
+        });
+    }
+
+    pub fn inter_channel(&self) -> Option<&InterChannel<f32>> {
+        self.analysis
+            .as_ref()
+            .map(|analysis| &analysis.inter_channel)
+    }
+
+    pub fn run(&mut self) {
+        loop {
+            let count = self
+                .left_out
+                .len()
+                .min(self.right_out.len())
+                .min(BLOCK_SIZE);
+            if count == 0 {
+                break;
+            }
+            let left_block = &mut self.left_block[..count];
+            let right_block = &mut self.right_block[..count];
+            self.left_out.pop_slice(left_block);
+            self.right_out.pop_slice(right_block);
+            self.sample_index += count as u64;
+
+            for (channel, producer) in self.outputs.iter_mut() {
+                let pushed = producer.push_iter(
+                    &mut left_block
+                        .iter()
+                        .zip(right_block.iter())
+                        .map(|(&left, &right)| channel.derive(left, right)),
+                );
+                self.stream_stats.add_dropped_samples(count - pushed);
+            }
+            if let Some(analysis) = &mut self.analysis {
+                // a window and a block always fit into the sample ringbuffers
+                analysis.left_samples_in.push_slice(left_block);
+                analysis.right_samples_in.push_slice(right_block);
+                Self::analyze(
+                    analysis,
+                    self.stream_stats.time(),
+                    self.left_out.len(),
+                    self.sample_rate,
+                    &mut self.level_in,
+                    &mut self.phase_in,
+                );
+            }
+        }
+    }
+
+    /// computes the columns of all complete windows,
+    /// `buffered` samples are left in the inputs
+    fn analyze(
+        analysis: &mut InterChannelAnalysis,
+        stream_time: Option<StreamTime>,
+        buffered: usize,
+        sample_rate: f32,
+        level_in: &mut SpscProducer<TimedColumn>,
+        phase_in: &mut SpscProducer<TimedColumn>,
+    ) {
+        let window_size = analysis.left_stft.window_size;
+        let step_size = analysis.left_stft.step_size;
+        let output_size = analysis.left_stft.output_size();
+        while analysis.left_samples_out.len() >= window_size {
+            let timestamp = column_timestamp(
+                stream_time,
+                analysis.sample_index,
+                buffered + analysis.left_samples_out.len(),
+                sample_rate,
+            );
+            copy_window(&analysis.left_samples_out, &mut analysis.left_window);
+            copy_window(&analysis.right_samples_out, &mut analysis.right_window);
+            analysis
+                .left_stft
+                .compute_into_complex_output(&analysis.left_window, &mut analysis.left_complex);
+            analysis
+                .right_stft
+                .compute_into_complex_output(&analysis.right_window, &mut analysis.right_complex);
+            let mut level_difference = recycled_column(level_in, output_size, 0.0);
+            let mut phase_difference = recycled_column(phase_in, output_size, 0.0);
+            analysis.inter_channel.compute(
+                &analysis.left_complex,
+                &analysis.right_complex,
+                &mut level_difference,
+                &mut phase_difference,
+            );
+            level_in.push((timestamp, level_difference));
+            phase_in.push((timestamp, phase_difference));
+            analysis.left_samples_out.skip(step_size);
+            analysis.right_samples_out.skip(step_size);
+            analysis.sample_index += step_size as u64;
+        }
+    }
+
+    pub fn stream_stats(&self) -> Arc<StreamStats> {
+        self.stream_stats.clone()
+    }
+
+    /// counts samples dropped by the outputs into `stream_stats`,
+    /// e.g. the counters of the `StftHandler`s reading them
+    pub fn set_stream_stats(&mut self, stream_stats: Arc<StreamStats>) {
+        self.stream_stats = stream_stats;
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// sample rate of the inputs, used for bin to frequency mapping
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// number of bins per inter-channel column, 0 if the analysis is disabled
+    pub fn output_size(&self) -> usize {
+        self.analysis
+            .as_ref()
+            .map_or(0, |analysis| analysis.left_stft.output_size())
+    }
+
+    /// center frequency in Hz of `bin` of the inter-channel columns
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        self.analysis.as_ref().map_or(0.0, |analysis| {
+            analysis.left_stft.bin_frequency(bin, self.sample_rate)
+        })
+    }
+
+    /// drains the level difference columns in dB with their timestamps
+    pub fn get_level_difference(&mut self) -> Vec<TimedColumn> {
+        let mut spec_vec = Vec::new();
+        while let Some(spectrum) = self.level_out.pop() {
+            spec_vec.push(spectrum);
+        }
+        spec_vec
+    }
+
+    /// hands columns of `get_level_difference` back to be reused by `run`
+    pub fn recycle_level_difference(&mut self, columns: Vec<Vec<f32>>) {
+        for column in columns {
+            self.level_out.recycle((Timestamp::default(), column));
+        }
+    }
+
+    /// drains the phase difference columns in radians with their timestamps
+    pub fn get_phase_difference(&mut self) -> Vec<TimedColumn> {
+        let mut spec_vec = Vec::new();
+        while let Some(spectrum) = self.phase_out.pop() {
+            spec_vec.push(spectrum);
+        }
+        spec_vec
+    }
+
+    /// hands columns of `get_phase_difference` back to be reused by `run`
+    pub fn recycle_phase_difference(&mut self, columns: Vec<Vec<f32>>) {
+        for column in columns {
+            self.phase_out.recycle((Timestamp::default(), column));
+        }
+    }
+}
@@ -2,7 +2,9 @@ use apodize;
 use itertools::izip;
 use num::complex::Complex;
 use num::traits::{Float, Signed, Zero};
-use realfft::{FftNum, RealFftPlanner, RealToComplex};
+use realfft::{ComplexToReal, FftNum, RealFftPlanner, RealToComplex};
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -19,11 +21,23 @@ use std::sync::Arc;
 /// * `return` - vector of single FFTs = spectrogram
 /// # ToDo: support overlapping windowsize
 pub fn calculate_stft(input_signal: &[f32], window_size: usize, step_size: usize) -> Vec<Vec<f32>> {
+    calculate_stft_with_scaling(input_signal, window_size, step_size, ScalingMode::default())
+}
+
+/// `calculate_stft` with selectable scaling,
+/// `ScalingMode::Linear` gives the input for the `mel` module
+pub fn calculate_stft_with_scaling(
+    input_signal: &[f32],
+    window_size: usize,
+    step_size: usize,
+    scaling: ScalingMode,
+) -> Vec<Vec<f32>> {
     let mut spectrogram: Vec<Vec<f32>> = Vec::new();
 
     // let's initialize our short-time fourier transform
     let window_type: WindowType = WindowType::Hanning;
     let mut stft = STFT::<f32>::new(window_type, window_size, step_size);
+    stft.set_scaling(scaling);
     let mut spectrogram_column: Vec<f32> = vec![0.0; stft.output_size()];
     // iterate over all the samples in chunks of step_size samples.
     for some_samples in input_signal.windows(window_size).step_by(step_size) {
@@ -33,6 +47,58 @@ pub fn calculate_stft(input_signal: &[f32], window_size: usize, step_size: usize
     spectrogram
 }
 
+/// `calculate_stft` keeping the complex columns instead of magnitudes,
+/// the input of `calculate_istft`
+pub fn calculate_complex_stft(
+    input_signal: &[f32],
+    window_size: usize,
+    step_size: usize,
+) -> Vec<Vec<Complex<f32>>> {
+    let mut spectrogram: Vec<Vec<Complex<f32>>> = Vec::new();
+
+    let window_type: WindowType = WindowType::Hanning;
+    let mut stft = STFT::<f32>::new(window_type, window_size, step_size);
+    let mut spectrogram_column = vec![Complex::new(0.0, 0.0); stft.complex_output_size()];
+    for some_samples in input_signal.windows(window_size).step_by(step_size) {
+        stft.compute_into_complex_output(some_samples, &mut spectrogram_column[..]);
+        spectrogram.push(spectrogram_column.clone());
+    }
+    spectrogram
+}
+
+/// easy to use function to resynthesize a signal from complex stft columns
+///
+/// counterpart of `calculate_complex_stft`, the columns are computed
+/// with `STFT::compute_into_complex_output` using a hanning window
+///
+/// * `spectrogram` - complex columns with `window_size / 2 + 1` bins each
+/// * `window_size` - length of the windows used for analysis
+/// * `step_size` - number of samples between two columns
+/// * `return` - signal with `(spectrogram.len() - 1) * step_size + window_size` samples
+pub fn calculate_istft(
+    spectrogram: &[Vec<Complex<f32>>],
+    window_size: usize,
+    step_size: usize,
+) -> Vec<f32> {
+    let mut signal: Vec<f32> = Vec::new();
+    if spectrogram.is_empty() {
+        return signal;
+    }
+
+    let window_type: WindowType = WindowType::Hanning;
+    let mut istft = ISTFT::<f32>::new(window_type, window_size, step_size);
+    let mut signal_chunk: Vec<f32> = vec![0.0; step_size];
+    for spectrogram_column in spectrogram {
+        istft.compute_column(spectrogram_column, &mut signal_chunk[..]);
+        signal.extend_from_slice(&signal_chunk);
+    }
+    // the rest of the last window
+    let mut signal_chunk: Vec<f32> = vec![0.0; istft.flush_size()];
+    istft.flush(&mut signal_chunk[..]);
+    signal.extend_from_slice(&signal_chunk);
+    signal
+}
+
 /// returns `0` if `log10(value).is_negative()`.
 /// otherwise returns `log10(value)`.
 /// `log10` turns values in domain `0..1` into values
@@ -53,29 +119,353 @@ pub fn log10_positive<T: Float + Signed + Zero>(value: T) -> T {
     }
 }
 
+/// how the magnitudes of a spectrogram column are scaled
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default)]
+pub enum ScalingMode {
+    /// `log10_positive(magnitude)`, values below 1.0 are set to zero
+    #[default]
+    Log10Positive,
+    /// plain magnitude
+    Linear,
+    /// squared magnitude
+    Power,
+    /// `20 * log10(magnitude / reference)`, clipped at `floor` (in dB)
+    Decibel { floor: f64, reference: f64 },
+    /// `ln(1 + magnitude)`, keeps quiet content above zero
+    Log1p,
+}
+
+impl ScalingMode {
+    pub const DEFAULT_DECIBEL_FLOOR: f64 = -120.0;
+    pub const DEFAULT_DECIBEL_REFERENCE: f64 = 1.0;
+
+    /// dB scaling relative to full scale
+    pub fn decibel() -> Self {
+        ScalingMode::Decibel {
+            floor: Self::DEFAULT_DECIBEL_FLOOR,
+            reference: Self::DEFAULT_DECIBEL_REFERENCE,
+        }
+    }
+
+    /// checks the dB parameters, `floor` has to be finite and below 0 dB,
+    /// `reference` finite and positive
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if let ScalingMode::Decibel { floor, reference } = *self {
+            if !floor.is_finite() || floor >= 0.0 {
+                return Err("dB floor has to be finite and negative");
+            }
+            if !reference.is_finite() || reference <= 0.0 {
+                return Err("dB reference has to be finite and positive");
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn apply<T: Float + Signed + FromF64>(&self, magnitude: T) -> T {
+        match *self {
+            ScalingMode::Log10Positive => log10_positive(magnitude),
+            ScalingMode::Linear => magnitude,
+            ScalingMode::Power => magnitude * magnitude,
+            ScalingMode::Decibel { floor, reference } => {
+                let floor: T = FromF64::from_f64(floor);
+                let reference: T = FromF64::from_f64(reference);
+                let twenty: T = FromF64::from_f64(20.0);
+                let decibel = twenty * (magnitude / reference).log10();
+                // log10(0) is -inf, max also takes care of NaN
+                decibel.max(floor)
+            }
+            ScalingMode::Log1p => magnitude.ln_1p(),
+        }
+    }
+
+    /// maps a scaled value into `0..=1` for display,
+    /// dB values are mapped linearly from `floor` to 0 dB
+    #[inline]
+    pub fn display_value(&self, value: f32) -> f32 {
+        match *self {
+            ScalingMode::Decibel { floor, .. } => {
+                let floor = floor as f32;
+                ((value - floor) / -floor).clamp(0.0, 1.0)
+            }
+            _ => value.abs().min(1.0),
+        }
+    }
+}
+
+impl FromStr for ScalingMode {
+    type Err = &'static str;
+
+    /// accepts `log10`, `linear`, `power`, `log1p`, `db`,
+    /// `db:<floor>` and `db:<floor>:<reference>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        let mut parts = lower.split(':');
+        let name = parts.next().unwrap_or("");
+        let scaling = match name {
+            "log10" => ScalingMode::Log10Positive,
+            "log10positive" => ScalingMode::Log10Positive,
+            "linear" => ScalingMode::Linear,
+            "power" => ScalingMode::Power,
+            "log1p" => ScalingMode::Log1p,
+            "db" | "decibel" => {
+                let floor = match parts.next() {
+                    Some(floor) => floor.parse().map_err(|_| "invalid dB floor")?,
+                    None => Self::DEFAULT_DECIBEL_FLOOR,
+                };
+                let reference = match parts.next() {
+                    Some(reference) => reference.parse().map_err(|_| "invalid dB reference")?,
+                    None => Self::DEFAULT_DECIBEL_REFERENCE,
+                };
+                ScalingMode::Decibel { floor, reference }
+            }
+            _ => return Err("no match"),
+        };
+        if parts.next().is_some() {
+            return Err("too many parameters");
+        }
+        scaling.validate()?;
+        Ok(scaling)
+    }
+}
+
+impl std::fmt::Display for ScalingMode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScalingMode::Log10Positive => write!(formatter, "log10"),
+            ScalingMode::Linear => write!(formatter, "linear"),
+            ScalingMode::Power => write!(formatter, "power"),
+            ScalingMode::Decibel { floor, reference } => {
+                write!(formatter, "db:{}:{}", floor, reference)
+            }
+            ScalingMode::Log1p => write!(formatter, "log1p"),
+        }
+    }
+}
+
+static SCALING_MODES: [ScalingMode; 5] = [
+    ScalingMode::Log10Positive,
+    ScalingMode::Linear,
+    ScalingMode::Power,
+    ScalingMode::Decibel {
+        floor: ScalingMode::DEFAULT_DECIBEL_FLOOR,
+        reference: ScalingMode::DEFAULT_DECIBEL_REFERENCE,
+    },
+    ScalingMode::Log1p,
+];
+
+impl ScalingMode {
+    pub fn values() -> [ScalingMode; 5] {
+        SCALING_MODES
+    }
+}
+
+/// how magnitude columns are computed
+#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash, Default)]
+pub enum SpectrogramMode {
+    /// plain stft magnitudes
+    #[default]
+    Standard,
+    /// time-frequency reassignment, energy of each bin is moved to the
+    /// center of gravity estimated from derivative-of-window and
+    /// time-ramped-window ffts. columns are delayed by
+    /// `STFT::reassignment_delay()` columns
+    Reassigned,
+}
+
+impl FromStr for SpectrogramMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        match &lower[..] {
+            "standard" => Ok(SpectrogramMode::Standard),
+            "stft" => Ok(SpectrogramMode::Standard),
+            "reassigned" => Ok(SpectrogramMode::Reassigned),
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for SpectrogramMode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+static SPECTROGRAM_MODES: [SpectrogramMode; 2] =
+    [SpectrogramMode::Standard, SpectrogramMode::Reassigned];
+
+impl SpectrogramMode {
+    pub fn values() -> [SpectrogramMode; 2] {
+        SPECTROGRAM_MODES
+    }
+}
+
+/// compensation of the window gain applied to magnitude columns
+#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash, Default)]
+pub enum WindowNormalization {
+    /// raw fft magnitudes
+    #[default]
+    None,
+    /// amplitude correct: a sine with amplitude 1.0 reads as 1.0 (0 dBFS)
+    /// independent of window type and window size
+    CoherentGain,
+    /// energy correct: broadband (noise) levels are comparable
+    /// across window types, the window's ENBW is taken into account
+    Energy,
+}
+
+impl FromStr for WindowNormalization {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        match &lower[..] {
+            "none" => Ok(WindowNormalization::None),
+            "coherentgain" => Ok(WindowNormalization::CoherentGain),
+            "amplitude" => Ok(WindowNormalization::CoherentGain),
+            "energy" => Ok(WindowNormalization::Energy),
+            "enbw" => Ok(WindowNormalization::Energy),
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for WindowNormalization {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+static WINDOW_NORMALIZATIONS: [WindowNormalization; 3] = [
+    WindowNormalization::None,
+    WindowNormalization::CoherentGain,
+    WindowNormalization::Energy,
+];
+
+impl WindowNormalization {
+    pub fn values() -> [WindowNormalization; 3] {
+        WINDOW_NORMALIZATIONS
+    }
+}
+
+/// fft implementation used for real input
+#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash, Default)]
+pub enum FftMode {
+    /// realfft, a half size complex fft of the real input
+    #[default]
+    RealFFT,
+    /// full size complex rustfft with a zero imaginary part,
+    /// always used for complex (analytic or IQ) input
+    RustFFT,
+}
+
+impl FromStr for FftMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        match &lower[..] {
+            "realfft" => Ok(FftMode::RealFFT),
+            "rustfft" => Ok(FftMode::RustFFT),
+            _ => Err("no match"),
+        }
+    }
+}
+
+impl std::fmt::Display for FftMode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{:?}", self)
+    }
+}
+
+static FFT_MODES: [FftMode; 2] = [FftMode::RealFFT, FftMode::RustFFT];
+
+impl FftMode {
+    pub fn values() -> [FftMode; 2] {
+        FFT_MODES
+    }
+}
+
 /// the type of apodization window to use
-#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
 pub enum WindowType {
     Hanning,
     Hamming,
+    /// apodize's blackman window, it uses the blackman-harris coefficients
     Blackman,
     Nuttall,
+    /// 4-term blackman-harris, -92 dB sidelobes
+    BlackmanHarris,
+    /// 5-term flat-top, for amplitude accurate measurements
+    FlatTop,
+    /// kaiser window with shape parameter `beta`
+    Kaiser(f64),
+    /// gaussian window with standard deviation `sigma` relative to half the window size
+    Gaussian(f64),
+    /// tukey (tapered cosine) window with taper ratio `alpha` in `0..=1`
+    Tukey(f64),
     None,
 }
 
+impl WindowType {
+    pub const DEFAULT_KAISER_BETA: f64 = 8.6;
+    pub const DEFAULT_GAUSSIAN_SIGMA: f64 = 0.4;
+    pub const DEFAULT_TUKEY_ALPHA: f64 = 0.5;
+}
+
 impl FromStr for WindowType {
     type Err = &'static str;
 
+    /// parameterized windows take their parameter after a colon,
+    /// e.g. `kaiser:8.6`, without it the default parameter is used
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let lower = s.to_lowercase();
-        match &lower[..] {
-            "hanning" => Ok(WindowType::Hanning),
-            "hann" => Ok(WindowType::Hanning),
-            "hamming" => Ok(WindowType::Hamming),
-            "blackman" => Ok(WindowType::Blackman),
-            "nuttall" => Ok(WindowType::Nuttall),
-            "none" => Ok(WindowType::None),
-            _ => Err("no match"),
+        let (name, parameter) = match lower.split_once(':') {
+            Some((name, parameter)) => {
+                let parameter: f64 = parameter.parse().map_err(|_| "invalid window parameter")?;
+                (name, Some(parameter))
+            }
+            None => (&lower[..], None),
+        };
+        let window_type = match name {
+            "hanning" => WindowType::Hanning,
+            "hann" => WindowType::Hanning,
+            "hamming" => WindowType::Hamming,
+            "blackman" => WindowType::Blackman,
+            "nuttall" => WindowType::Nuttall,
+            "blackmanharris" => WindowType::BlackmanHarris,
+            "blackman-harris" => WindowType::BlackmanHarris,
+            "flattop" => WindowType::FlatTop,
+            "flat-top" => WindowType::FlatTop,
+            "kaiser" => {
+                let beta = parameter.unwrap_or(Self::DEFAULT_KAISER_BETA);
+                if !beta.is_finite() || beta < 0.0 {
+                    return Err("kaiser beta has to be finite and not negative");
+                }
+                return Ok(WindowType::Kaiser(beta));
+            }
+            "gaussian" => {
+                let sigma = parameter.unwrap_or(Self::DEFAULT_GAUSSIAN_SIGMA);
+                if !sigma.is_finite() || sigma <= 0.0 {
+                    return Err("gaussian sigma has to be finite and positive");
+                }
+                return Ok(WindowType::Gaussian(sigma));
+            }
+            "tukey" => {
+                let alpha = parameter.unwrap_or(Self::DEFAULT_TUKEY_ALPHA);
+                if !(0.0..=1.0).contains(&alpha) {
+                    return Err("tukey alpha has to be in 0..=1");
+                }
+                return Ok(WindowType::Tukey(alpha));
+            }
+            "none" => WindowType::None,
+            _ => return Err("no match"),
+        };
+        match parameter {
+            Some(_) => Err("window takes no parameter"),
+            None => Ok(window_type),
         }
     }
 }
@@ -83,46 +473,197 @@ impl FromStr for WindowType {
 // this also implements ToString::to_string
 impl std::fmt::Display for WindowType {
     fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(formatter, "{:?}", self)
+        match self {
+            WindowType::Kaiser(beta) => write!(formatter, "Kaiser:{}", beta),
+            WindowType::Gaussian(sigma) => write!(formatter, "Gaussian:{}", sigma),
+            WindowType::Tukey(alpha) => write!(formatter, "Tukey:{}", alpha),
+            _ => write!(formatter, "{:?}", self),
+        }
     }
 }
 
 // TODO write a macro that does this automatically for any enum
-static WINDOW_TYPES: [WindowType; 5] = [
+static WINDOW_TYPES: [WindowType; 10] = [
     WindowType::Hanning,
     WindowType::Hamming,
     WindowType::Blackman,
     WindowType::Nuttall,
+    WindowType::BlackmanHarris,
+    WindowType::FlatTop,
+    WindowType::Kaiser(WindowType::DEFAULT_KAISER_BETA),
+    WindowType::Gaussian(WindowType::DEFAULT_GAUSSIAN_SIGMA),
+    WindowType::Tukey(WindowType::DEFAULT_TUKEY_ALPHA),
     WindowType::None,
 ];
 
 impl WindowType {
-    pub fn values() -> [WindowType; 5] {
+    /// all window types, parameterized ones with their default parameter
+    pub fn values() -> [WindowType; 10] {
         WINDOW_TYPES
     }
 }
 
+/// zeroth order modified bessel function of the first kind,
+/// power series, converges fast for the arguments used by kaiser windows
+fn bessel_i0(x: f64) -> f64 {
+    let half_x = x / 2.0;
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-16 {
+        term *= (half_x / k) * (half_x / k);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// symmetric windows like apodize: `x` runs from `0` to `1` over `size` values
+fn window_positions(size: usize) -> impl Iterator<Item = f64> {
+    assert!(1 < size);
+    (0..size).map(move |index| index as f64 / (size - 1) as f64)
+}
+
+fn flat_top_iter(size: usize) -> impl Iterator<Item = f64> {
+    // coefficients of the matlab flattopwin
+    const A: [f64; 5] = [
+        0.215_578_95,
+        0.416_631_58,
+        0.277_263_158,
+        0.083_578_947,
+        0.006_947_368,
+    ];
+    window_positions(size).map(|x| {
+        let phi = 2.0 * std::f64::consts::PI * x;
+        A[0] - A[1] * phi.cos() + A[2] * (2.0 * phi).cos() - A[3] * (3.0 * phi).cos()
+            + A[4] * (4.0 * phi).cos()
+    })
+}
+
+fn kaiser_iter(size: usize, beta: f64) -> impl Iterator<Item = f64> {
+    let denominator = bessel_i0(beta);
+    window_positions(size).map(move |x| {
+        let r = 2.0 * x - 1.0;
+        bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / denominator
+    })
+}
+
+fn gaussian_iter(size: usize, sigma: f64) -> impl Iterator<Item = f64> {
+    window_positions(size).map(move |x| {
+        let r = (2.0 * x - 1.0) / sigma;
+        (-0.5 * r * r).exp()
+    })
+}
+
+fn tukey_iter(size: usize, alpha: f64) -> impl Iterator<Item = f64> {
+    window_positions(size).map(move |x| {
+        // distance to the nearest edge, 0..=0.5
+        let edge = x.min(1.0 - x);
+        if alpha <= 0.0 || edge >= alpha / 2.0 {
+            1.0
+        } else {
+            0.5 * (1.0 - (2.0 * std::f64::consts::PI * edge / alpha).cos())
+        }
+    })
+}
+
 pub struct STFT<T>
 where
     T: FftNum + FromF64 + num::Float,
 {
     pub window_size: usize,
+    /// length of the fft, `fft_size - window_size` zeros are padded
+    pub fft_size: usize,
     pub step_size: usize,
     pub rfft: Arc<dyn RealToComplex<T>>,
     pub window: Option<Vec<T>>,
     pub real_input: Vec<T>,
     pub scratch_space: Vec<Complex<T>>,
+    /// complex column reused by `compute_column` and `compute_magnitude_column`
+    pub complex_output: Vec<Complex<T>>,
+    pub scaling: ScalingMode,
+    pub normalization: WindowNormalization,
+    /// factor applied to magnitudes, derived from `normalization` and `window`
+    pub normalization_factor: T,
+    /// output the nyquist bin as well: `fft_size / 2 + 1` instead of `fft_size / 2` values
+    pub include_nyquist: bool,
+    pub mode: SpectrogramMode,
+    /// state of `SpectrogramMode::Reassigned`
+    pub reassignment: Option<Reassignment<T>>,
+    pub fft_mode: FftMode,
+    /// state of the complex fft, used by `FftMode::RustFFT` and for complex input
+    pub complex_fft: Option<ComplexFft<T>>,
+}
+
+/// planned complex fft of `fft_size` and its buffers
+pub struct ComplexFft<T> {
+    pub fft: Arc<dyn Fft<T>>,
+    pub buffer: Vec<Complex<T>>,
+    pub scratch_space: Vec<Complex<T>>,
+}
+
+impl<T> ComplexFft<T>
+where
+    T: FftNum,
+{
+    pub fn new(fft_size: usize) -> Self {
+        let mut planner = FftPlanner::<T>::new();
+        let fft = planner.plan_fft_forward(fft_size);
+        let scratch_space = vec![Complex::new(T::zero(), T::zero()); fft.get_inplace_scratch_len()];
+        ComplexFft {
+            fft,
+            buffer: vec![Complex::new(T::zero(), T::zero()); fft_size],
+            scratch_space,
+        }
+    }
+
+    /// transforms `buffer` in place
+    pub fn process(&mut self) {
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch_space);
+    }
+}
+
+/// buffers needed for time-frequency reassignment
+pub struct Reassignment<T> {
+    /// derivative of the window in 1/samples
+    pub derivative_window: Vec<T>,
+    /// window multiplied with the time in samples relative to the window center
+    pub ramp_window: Vec<T>,
+    pub real_input: Vec<T>,
+    pub derivative_output: Vec<Complex<T>>,
+    pub ramp_output: Vec<Complex<T>>,
+    /// power accumulators of the columns which can still receive energy,
+    /// the front is the next column to output
+    pub columns: VecDeque<Vec<T>>,
 }
 
 impl<T> STFT<T>
 where
     T: FftNum + FromF64 + num::Float + std::ops::MulAssign,
 {
+    /// # Panics
+    /// panics if `window_type` has a window and `window_size < 2`,
+    /// `try_window_type_to_window_vec` returns an error instead
     pub fn window_type_to_window_vec(
         window_type: WindowType,
         window_size: usize,
     ) -> Option<Vec<T>> {
-        match window_type {
+        match Self::try_window_type_to_window_vec(window_type, window_size) {
+            Ok(window) => window,
+            Err(message) => panic!("{}", message),
+        }
+    }
+
+    /// `None` for `WindowType::None`, fails for windows of less than 2 values
+    pub fn try_window_type_to_window_vec(
+        window_type: WindowType,
+        window_size: usize,
+    ) -> Result<Option<Vec<T>>, &'static str> {
+        if window_type != WindowType::None && window_size < 2 {
+            return Err("window size has to be at least 2");
+        }
+        let window = match window_type {
             WindowType::Hanning => Some(
                 apodize::hanning_iter(window_size)
                     .map(FromF64::from_f64)
@@ -143,8 +684,32 @@ where
                     .map(FromF64::from_f64)
                     .collect(),
             ),
+            WindowType::BlackmanHarris => Some(
+                apodize::cosine_iter(0.35875, 0.48829, 0.14128, 0.01168, window_size)
+                    .map(FromF64::from_f64)
+                    .collect(),
+            ),
+            WindowType::FlatTop => {
+                Some(flat_top_iter(window_size).map(FromF64::from_f64).collect())
+            }
+            WindowType::Kaiser(beta) => Some(
+                kaiser_iter(window_size, beta)
+                    .map(FromF64::from_f64)
+                    .collect(),
+            ),
+            WindowType::Gaussian(sigma) => Some(
+                gaussian_iter(window_size, sigma)
+                    .map(FromF64::from_f64)
+                    .collect(),
+            ),
+            WindowType::Tukey(alpha) => Some(
+                tukey_iter(window_size, alpha)
+                    .map(FromF64::from_f64)
+                    .collect(),
+            ),
             WindowType::None => None,
-        }
+        };
+        Ok(window)
     }
 
     pub fn new(window_type: WindowType, window_size: usize, step_size: usize) -> Self {
@@ -152,38 +717,249 @@ where
         Self::new_with_window_vec(window, window_size, step_size)
     }
 
+    /// stft with windows of `window_size` zero-padded to `fft_size`
+    pub fn new_with_fft_size(
+        window_type: WindowType,
+        window_size: usize,
+        fft_size: usize,
+        step_size: usize,
+    ) -> Self {
+        let window = Self::window_type_to_window_vec(window_type, window_size);
+        Self::new_with_window_vec_and_fft_size(window, window_size, fft_size, step_size)
+    }
+
     // TODO this should ideally take an iterator and not a vec
     pub fn new_with_window_vec(
         window: Option<Vec<T>>,
         window_size: usize,
         step_size: usize,
+    ) -> Self {
+        Self::new_with_window_vec_and_fft_size(window, window_size, window_size, step_size)
+    }
+
+    pub fn new_with_window_vec_and_fft_size(
+        window: Option<Vec<T>>,
+        window_size: usize,
+        fft_size: usize,
+        step_size: usize,
     ) -> Self {
         // TODO more assertions:
         // window_size is power of two
         // step_size > 0
         assert!(step_size <= window_size);
+        assert!(window_size <= fft_size);
         let mut real_planner = RealFftPlanner::<T>::new();
-        let rfft = real_planner.plan_fft_forward(window_size);
+        let rfft = real_planner.plan_fft_forward(fft_size);
         let scratch_space = rfft.make_scratch_vec();
         let real_input = rfft.make_input_vec();
+        let complex_output = rfft.make_output_vec();
 
         STFT {
             window_size: window_size,
+            fft_size,
             step_size: step_size,
             rfft: rfft,
             window: window,
             real_input: real_input,
             scratch_space: scratch_space,
+            complex_output,
+            scaling: ScalingMode::default(),
+            normalization: WindowNormalization::default(),
+            normalization_factor: T::one(),
+            include_nyquist: false,
+            mode: SpectrogramMode::default(),
+            reassignment: None,
+            fft_mode: FftMode::default(),
+            complex_fft: None,
+        }
+    }
+
+    /// selects the fft implementation for real input,
+    /// the complex fft is planned on first use
+    pub fn set_fft_mode(&mut self, fft_mode: FftMode) {
+        if fft_mode == FftMode::RustFFT && self.complex_fft.is_none() {
+            self.complex_fft = Some(ComplexFft::new(self.fft_size));
         }
+        self.fft_mode = fft_mode;
     }
 
+    /// selects how `compute_column` computes magnitudes,
+    /// switching mode resets the reassignment history
+    pub fn set_mode(&mut self, mode: SpectrogramMode) {
+        self.reassignment = match mode {
+            SpectrogramMode::Standard => None,
+            SpectrogramMode::Reassigned => Some(self.make_reassignment()),
+        };
+        self.mode = mode;
+    }
+
+    /// number of columns a reassigned column is delayed,
+    /// energy can be moved by up to half a window in time
+    pub fn reassignment_delay(&self) -> usize {
+        (self.window_size / 2).div_ceil(self.step_size)
+    }
+
+    fn make_reassignment(&self) -> Reassignment<T> {
+        let window: Vec<T> = match self.window {
+            Some(ref window) => window.clone(),
+            None => vec![T::one(); self.window_size],
+        };
+        let half: T = FromF64::from_f64(0.5);
+        let last = self.window_size - 1;
+        let derivative_window = (0..self.window_size)
+            .map(|index| {
+                let previous = if index > 0 {
+                    window[index - 1]
+                } else {
+                    T::zero()
+                };
+                let next = if index < last {
+                    window[index + 1]
+                } else {
+                    T::zero()
+                };
+                (next - previous) * half
+            })
+            .collect();
+        let center: T = FromF64::from_f64(last as f64 / 2.0);
+        let ramp_window = window
+            .iter()
+            .enumerate()
+            .map(|(index, &window_elem)| {
+                let time: T = FromF64::from_f64(index as f64);
+                (time - center) * window_elem
+            })
+            .collect();
+        let column_count = 2 * self.reassignment_delay() + 1;
+        Reassignment {
+            derivative_window,
+            ramp_window,
+            real_input: self.rfft.make_input_vec(),
+            derivative_output: self.rfft.make_output_vec(),
+            ramp_output: self.rfft.make_output_vec(),
+            columns: (0..column_count)
+                .map(|_| vec![T::zero(); self.complex_output_size()])
+                .collect(),
+        }
+    }
+
+    /// switches between `fft_size / 2` and the full `fft_size / 2 + 1` output bins
+    pub fn set_include_nyquist(&mut self, include_nyquist: bool) {
+        self.include_nyquist = include_nyquist;
+    }
+
+    /// coherent gain of a window: `sum(w) / N`, `1.0` for no window
+    pub fn coherent_gain(window: Option<&[T]>, window_size: usize) -> T {
+        match window {
+            Some(window) => {
+                let sum = window.iter().fold(T::zero(), |acc, &elem| acc + elem);
+                sum / FromF64::from_f64(window_size as f64)
+            }
+            None => T::one(),
+        }
+    }
+
+    /// equivalent noise bandwidth of a window in bins: `N * sum(w^2) / sum(w)^2`
+    pub fn equivalent_noise_bandwidth(window: Option<&[T]>, window_size: usize) -> T {
+        match window {
+            Some(window) => {
+                let sum = window.iter().fold(T::zero(), |acc, &elem| acc + elem);
+                let square_sum = window
+                    .iter()
+                    .fold(T::zero(), |acc, &elem| acc + elem * elem);
+                let size: T = FromF64::from_f64(window_size as f64);
+                size * square_sum / (sum * sum)
+            }
+            None => T::one(),
+        }
+    }
+
+    /// sets the window normalization applied by
+    /// `compute_column` and `compute_magnitude_column`
+    pub fn set_normalization(&mut self, normalization: WindowNormalization) {
+        let size: T = FromF64::from_f64(self.window_size as f64);
+        let two: T = FromF64::from_f64(2.0);
+        let window = self.window.as_deref();
+        self.normalization_factor = match normalization {
+            WindowNormalization::None => T::one(),
+            // single sided amplitude: A * sum(w) / 2 => A
+            WindowNormalization::CoherentGain => {
+                two / (size * Self::coherent_gain(window, self.window_size))
+            }
+            // single sided rms: sqrt(2 / sum(w^2)) = sqrt(2 / (N * cg^2 * enbw))
+            WindowNormalization::Energy => {
+                let coherent_gain = Self::coherent_gain(window, self.window_size);
+                let enbw = Self::equivalent_noise_bandwidth(window, self.window_size);
+                (two / (size * coherent_gain * coherent_gain * enbw)).sqrt()
+            }
+        };
+        self.normalization = normalization;
+    }
+
+    /// sets the scaling applied by `compute_column`
+    pub fn set_scaling(&mut self, scaling: ScalingMode) {
+        self.scaling = scaling;
+    }
+
+    /// number of values of a magnitude column
     #[inline]
     pub fn output_size(&self) -> usize {
-        self.window_size / 2
+        if self.include_nyquist {
+            self.fft_size / 2 + 1
+        } else {
+            self.fft_size / 2
+        }
+    }
+
+    /// number of bins of a complex column, always including the nyquist bin
+    #[inline]
+    pub fn complex_output_size(&self) -> usize {
+        self.fft_size / 2 + 1
+    }
+
+    /// center frequency in Hz of `bin`
+    #[inline]
+    pub fn bin_frequency(&self, bin: usize, sample_rate: f32) -> f32 {
+        bin as f32 * sample_rate / self.fft_size as f32
+    }
+
+    /// nearest bin of `frequency` in Hz, clamped to the bins of a complex column
+    #[inline]
+    pub fn frequency_bin(&self, frequency: f32, sample_rate: f32) -> usize {
+        let bin = (frequency * self.fft_size as f32 / sample_rate).round();
+        (bin.max(0.0) as usize).min(self.fft_size / 2)
+    }
+
+    /// number of bins of a column of complex input, negative and positive frequencies
+    #[inline]
+    pub fn iq_output_size(&self) -> usize {
+        self.fft_size
     }
 
+    /// center frequency in Hz of `bin` of a column of complex input,
+    /// bin `fft_size / 2` is 0 Hz
+    #[inline]
+    pub fn iq_bin_frequency(&self, bin: usize, sample_rate: f32) -> f32 {
+        (bin as f32 - (self.fft_size / 2) as f32) * sample_rate / self.fft_size as f32
+    }
+
+    /// nearest bin of `frequency` in Hz in a column of complex input
+    #[inline]
+    pub fn iq_frequency_bin(&self, frequency: f32, sample_rate: f32) -> usize {
+        let bin =
+            (frequency * self.fft_size as f32 / sample_rate).round() + (self.fft_size / 2) as f32;
+        (bin.max(0.0) as usize).min(self.fft_size - 1)
+    }
+
+    /// `input` holds `window_size` samples,
+    /// `output` has to hold `fft_size / 2 + 1` bins.
+    /// uses realfft or rustfft depending on `self.fft_mode`, the results are equal
     pub fn compute_into_complex_output(&mut self, input: &[T], output: &mut [Complex<T>]) {
         assert_eq!(input.len(), self.window_size);
+        if self.fft_mode == FftMode::RustFFT {
+            self.compute_with_complex_fft(input, output);
+            return;
+        }
 
         // multiply real_input with window
         if let Some(ref window) = self.window {
@@ -197,35 +973,464 @@ where
                 *dst = *src;
             }
         };
+        // zero padding, the fft uses real_input as scratch so it is refilled every time
+        for dst in self.real_input[self.window_size..].iter_mut() {
+            *dst = T::zero();
+        }
         // compute fft
         let _ =
             self.rfft
                 .process_with_scratch(&mut self.real_input, output, &mut self.scratch_space);
     }
 
+    fn compute_with_complex_fft(&mut self, input: &[T], output: &mut [Complex<T>]) {
+        assert_eq!(self.complex_output_size(), output.len());
+        let fft_size = self.fft_size;
+        let complex_fft = self
+            .complex_fft
+            .get_or_insert_with(|| ComplexFft::new(fft_size));
+        match self.window {
+            Some(ref window) => {
+                for (dst, src, window_elem) in
+                    izip!(complex_fft.buffer.iter_mut(), input.iter(), window.iter())
+                {
+                    *dst = Complex::new(*src * *window_elem, T::zero());
+                }
+            }
+            None => {
+                for (dst, src) in complex_fft.buffer.iter_mut().zip(input.iter()) {
+                    *dst = Complex::new(*src, T::zero());
+                }
+            }
+        }
+        for dst in complex_fft.buffer[self.window_size..].iter_mut() {
+            *dst = Complex::new(T::zero(), T::zero());
+        }
+        complex_fft.process();
+        output.copy_from_slice(&complex_fft.buffer[..output.len()]);
+    }
+
+    /// complex (analytic or IQ) `input` of `window_size` samples,
+    /// always uses rustfft.
+    /// `output` has to hold `fft_size` bins, ordered from `-fs / 2` to `fs / 2`,
+    /// see `iq_bin_frequency`
+    pub fn compute_iq_into_complex_output(
+        &mut self,
+        input: &[Complex<T>],
+        output: &mut [Complex<T>],
+    ) {
+        assert_eq!(input.len(), self.window_size);
+        assert_eq!(self.iq_output_size(), output.len());
+        let fft_size = self.fft_size;
+        let complex_fft = self
+            .complex_fft
+            .get_or_insert_with(|| ComplexFft::new(fft_size));
+        match self.window {
+            Some(ref window) => {
+                for (dst, src, window_elem) in
+                    izip!(complex_fft.buffer.iter_mut(), input.iter(), window.iter())
+                {
+                    *dst = src.scale(*window_elem);
+                }
+            }
+            None => complex_fft.buffer[..self.window_size].copy_from_slice(input),
+        }
+        for dst in complex_fft.buffer[self.window_size..].iter_mut() {
+            *dst = Complex::new(T::zero(), T::zero());
+        }
+        complex_fft.process();
+        // negative frequencies first
+        let half = fft_size / 2;
+        output[..fft_size - half].copy_from_slice(&complex_fft.buffer[half..]);
+        output[fft_size - half..].copy_from_slice(&complex_fft.buffer[..half]);
+    }
+
+    /// turns a complex column of `compute_iq_into_complex_output` into a spectrogram column,
+    /// with `WindowNormalization::CoherentGain` a complex exponential
+    /// with amplitude 1.0 reads as 1.0
+    /// # Panics
+    /// panics unless `self.iq_output_size() == output.len()`
+    pub fn iq_to_column(&self, complex_column: &[Complex<T>], output: &mut [T]) {
+        assert_eq!(self.iq_output_size(), output.len());
+        // the normalization factor is single sided, the energy isn't split between two bins
+        let two_sided_factor: T = match self.normalization {
+            WindowNormalization::None => T::one(),
+            WindowNormalization::CoherentGain => FromF64::from_f64(0.5),
+            WindowNormalization::Energy => FromF64::from_f64(std::f64::consts::FRAC_1_SQRT_2),
+        };
+        let normalization_factor = self.normalization_factor * two_sided_factor;
+        for (dst, src) in output.iter_mut().zip(complex_column.iter()) {
+            *dst = self.scaling.apply(src.norm() * normalization_factor);
+        }
+    }
+
     /// # Panics
     /// panics unless `self.output_size() == output.len()`
     pub fn compute_magnitude_column(&mut self, input: &mut [T], output: &mut [T]) {
         assert_eq!(self.output_size(), output.len());
-        let mut complex_output = self.rfft.make_output_vec();
+        let mut complex_output = std::mem::take(&mut self.complex_output);
         self.compute_into_complex_output(input, &mut complex_output);
 
         for (dst, src) in output.iter_mut().zip(complex_output.iter()) {
-            *dst = src.norm();
+            *dst = src.norm() * self.normalization_factor;
         }
+        self.complex_output = complex_output;
     }
 
-    /// computes a column of the spectrogram
+    /// computes a column of the spectrogram,
+    /// magnitudes are normalized according to `self.normalization`
+    /// and scaled according to `self.scaling`
     /// # Panics
     /// panics unless `self.output_size() == output.len()`
     pub fn compute_column(&mut self, input: &[T], output: &mut [T]) {
         assert_eq!(self.output_size(), output.len());
 
-        let mut complex_output = self.rfft.make_output_vec();
+        // taken out of `self` while the column is computed, keeps its allocation
+        let mut complex_output = std::mem::take(&mut self.complex_output);
         self.compute_into_complex_output(input, &mut complex_output);
+        match self.mode {
+            SpectrogramMode::Standard => self.complex_to_column(&complex_output, output),
+            SpectrogramMode::Reassigned => self.reassign_column(input, &complex_output, output),
+        }
+        self.complex_output = complex_output;
+    }
 
-        for (dst, src) in output.iter_mut().zip(complex_output.iter()) {
-            *dst = log10_positive(src.norm());
+    /// turns a complex column of `compute_into_complex_output` into
+    /// a spectrogram column like `compute_column` does
+    /// # Panics
+    /// panics unless `self.output_size() == output.len()`
+    pub fn complex_to_column(&self, complex_column: &[Complex<T>], output: &mut [T]) {
+        assert_eq!(self.output_size(), output.len());
+
+        for (dst, src) in output.iter_mut().zip(complex_column.iter()) {
+            *dst = self.scaling.apply(src.norm() * self.normalization_factor);
+        }
+    }
+
+    /// normalized linear magnitudes of a complex column, without scaling,
+    /// `output` can hold up to `self.complex_output_size()` values
+    pub fn complex_to_magnitudes(&self, complex_column: &[Complex<T>], output: &mut [T]) {
+        for (dst, src) in output.iter_mut().zip(complex_column.iter()) {
+            *dst = src.norm() * self.normalization_factor;
+        }
+    }
+
+    /// reassigned counterpart of `complex_to_column`, `complex_column` has to be
+    /// the result of `compute_into_complex_output` for `input`.
+    /// outputs the column `self.reassignment_delay()` calls before this one
+    /// # Panics
+    /// panics unless `self.output_size() == output.len()`
+    pub fn reassign_column(
+        &mut self,
+        input: &[T],
+        complex_column: &[Complex<T>],
+        output: &mut [T],
+    ) {
+        assert_eq!(self.output_size(), output.len());
+        assert_eq!(input.len(), self.window_size);
+        if self.reassignment.is_none() {
+            self.reassignment = Some(self.make_reassignment());
+        }
+        let delay = self.reassignment_delay();
+        let step_size: T = FromF64::from_f64(self.step_size as f64);
+        let bins_per_radian: T =
+            FromF64::from_f64(self.fft_size as f64 / (2.0 * std::f64::consts::PI));
+        let last_bin: T = FromF64::from_f64((self.complex_output_size() - 1) as f64);
+        let last_column: T = FromF64::from_f64((2 * delay) as f64);
+        let epsilon: T = FromF64::from_f64(1e-20);
+        let normalization_factor = self.normalization_factor;
+        let window_size = self.window_size;
+        let reassignment = self.reassignment.as_mut().expect("reassignment state");
+
+        // ffts with derivative and time-ramped window
+        for (derivative_window, derivative_output) in [
+            (
+                &reassignment.derivative_window,
+                &mut reassignment.derivative_output,
+            ),
+            (&reassignment.ramp_window, &mut reassignment.ramp_output),
+        ] {
+            for (dst, src, window_elem) in izip!(
+                reassignment.real_input.iter_mut(),
+                input.iter(),
+                derivative_window.iter()
+            ) {
+                *dst = *src * *window_elem;
+            }
+            for dst in reassignment.real_input[window_size..].iter_mut() {
+                *dst = T::zero();
+            }
+            let _ = self.rfft.process_with_scratch(
+                &mut reassignment.real_input,
+                derivative_output,
+                &mut self.scratch_space,
+            );
+        }
+
+        // move the energy of every bin to its reassigned position
+        for (bin, (x, x_derivative, x_ramp)) in izip!(
+            complex_column.iter(),
+            reassignment.derivative_output.iter(),
+            reassignment.ramp_output.iter()
+        )
+        .enumerate()
+        {
+            let power = x.norm_sqr();
+            if power < epsilon {
+                continue;
+            }
+            let conjugate = x.conj();
+            let frequency_offset = -(x_derivative * conjugate).im / power * bins_per_radian;
+            let time_offset = (x_ramp * conjugate).re / power / step_size;
+            let bin: T = FromF64::from_f64(bin as f64);
+            let column: T = FromF64::from_f64(delay as f64);
+            let reassigned_bin = (bin + frequency_offset).round();
+            let reassigned_column = (column + time_offset).round();
+            if reassigned_bin < T::zero()
+                || reassigned_bin > last_bin
+                || reassigned_column < T::zero()
+                || reassigned_column > last_column
+            {
+                continue;
+            }
+            let (Some(reassigned_bin), Some(reassigned_column)) =
+                (reassigned_bin.to_usize(), reassigned_column.to_usize())
+            else {
+                continue;
+            };
+            reassignment.columns[reassigned_column][reassigned_bin] =
+                reassignment.columns[reassigned_column][reassigned_bin] + power;
+        }
+
+        // the oldest column is complete
+        let mut finished = reassignment
+            .columns
+            .pop_front()
+            .expect("reassignment columns");
+        for (dst, src) in output.iter_mut().zip(finished.iter()) {
+            *dst = self.scaling.apply(src.sqrt() * normalization_factor);
+        }
+        for elem in finished.iter_mut() {
+            *elem = T::zero();
+        }
+        reassignment.columns.push_back(finished);
+    }
+}
+
+/// inverse short time fourier transform
+///
+/// takes complex columns as produced by `STFT::compute_into_complex_output`,
+/// applies the synthesis window and overlap-adds them (weighted overlap-add).
+/// every column yields the next `step_size` samples of the signal,
+/// these are the first `step_size` samples of the column's analysis window
+pub struct ISTFT<T>
+where
+    T: FftNum + FromF64 + num::Float,
+{
+    pub window_size: usize,
+    /// length of the inverse fft, only the first `window_size` samples are used
+    pub fft_size: usize,
+    pub step_size: usize,
+    pub irfft: Arc<dyn ComplexToReal<T>>,
+    pub window: Option<Vec<T>>,
+    pub complex_input: Vec<Complex<T>>,
+    pub real_output: Vec<T>,
+    pub scratch_space: Vec<Complex<T>>,
+    /// overlap-add accumulator of the windowed frames
+    pub overlap_buffer: Vec<T>,
+    /// overlap-add accumulator of analysis * synthesis window
+    pub window_sum_buffer: Vec<T>,
+}
+
+impl<T> ISTFT<T>
+where
+    T: FftNum + FromF64 + num::Float + std::ops::MulAssign,
+{
+    pub fn new(window_type: WindowType, window_size: usize, step_size: usize) -> Self {
+        let window = STFT::<T>::window_type_to_window_vec(window_type, window_size);
+        Self::new_with_window_vec(window, window_size, step_size)
+    }
+
+    /// inverse of `STFT::new_with_fft_size`
+    pub fn new_with_fft_size(
+        window_type: WindowType,
+        window_size: usize,
+        fft_size: usize,
+        step_size: usize,
+    ) -> Self {
+        let window = STFT::<T>::window_type_to_window_vec(window_type, window_size);
+        Self::new_with_window_vec_and_fft_size(window, window_size, fft_size, step_size)
+    }
+
+    /// `window` is used as analysis and synthesis window,
+    /// so it has to be the same as the one of the `STFT`
+    pub fn new_with_window_vec(
+        window: Option<Vec<T>>,
+        window_size: usize,
+        step_size: usize,
+    ) -> Self {
+        Self::new_with_window_vec_and_fft_size(window, window_size, window_size, step_size)
+    }
+
+    pub fn new_with_window_vec_and_fft_size(
+        window: Option<Vec<T>>,
+        window_size: usize,
+        fft_size: usize,
+        step_size: usize,
+    ) -> Self {
+        assert!(step_size > 0);
+        assert!(step_size <= window_size);
+        assert!(window_size <= fft_size);
+        let mut real_planner = RealFftPlanner::<T>::new();
+        let irfft = real_planner.plan_fft_inverse(fft_size);
+        let scratch_space = irfft.make_scratch_vec();
+        let complex_input = irfft.make_input_vec();
+        let real_output = irfft.make_output_vec();
+
+        ISTFT {
+            window_size,
+            fft_size,
+            step_size,
+            irfft,
+            window,
+            complex_input,
+            real_output,
+            scratch_space,
+            overlap_buffer: vec![T::zero(); window_size],
+            window_sum_buffer: vec![T::zero(); window_size],
+        }
+    }
+
+    /// number of complex bins expected per column
+    #[inline]
+    pub fn input_size(&self) -> usize {
+        self.fft_size / 2 + 1
+    }
+
+    /// number of samples still buffered after a column, returned by `flush`
+    #[inline]
+    pub fn flush_size(&self) -> usize {
+        self.window_size - self.step_size
+    }
+
+    /// checks if analysis * synthesis window overlap-adds to a constant
+    /// for `step_size` (constant overlap-add condition).
+    /// if not, the reconstruction is still exact in this implementation,
+    /// but spectral modifications will be weighted unequally over time
+    pub fn is_cola(window_type: WindowType, window_size: usize, step_size: usize) -> bool {
+        let window = STFT::<T>::window_type_to_window_vec(window_type, window_size);
+        Self::is_cola_window_vec(window.as_deref(), window_size, step_size)
+    }
+
+    pub fn is_cola_window_vec(window: Option<&[T]>, window_size: usize, step_size: usize) -> bool {
+        assert!(step_size > 0);
+        let mut sums = vec![T::zero(); step_size];
+        for (index, sum) in sums.iter_mut().enumerate() {
+            for position in (index..window_size).step_by(step_size) {
+                let window_elem = match window {
+                    Some(window) => window[position],
+                    None => T::one(),
+                };
+                *sum = *sum + window_elem * window_elem;
+            }
+        }
+        let max = sums.iter().fold(T::zero(), |acc, &sum| acc.max(sum));
+        let min = sums.iter().fold(max, |acc, &sum| acc.min(sum));
+        let tolerance: T = FromF64::from_f64(1e-3);
+        max > T::zero() && (max - min) <= tolerance * max
+    }
+
+    /// resynthesizes the next `step_size` samples of the signal
+    /// # Panics
+    /// panics unless `self.input_size() == input.len()`
+    /// and `self.step_size == output.len()`
+    pub fn compute_column(&mut self, input: &[Complex<T>], output: &mut [T]) {
+        assert_eq!(self.input_size(), input.len());
+        assert_eq!(self.step_size, output.len());
+
+        self.complex_input.copy_from_slice(input);
+        // dc and nyquist bins have to be real for a real output signal
+        self.complex_input[0].im = T::zero();
+        // the last bin is only the nyquist bin for even fft sizes
+        let has_nyquist = 2 * (self.complex_input.len() - 1) == self.fft_size;
+        if let Some(nyquist) = self.complex_input.last_mut() {
+            if has_nyquist {
+                nyquist.im = T::zero();
+            }
+        }
+        // compute inverse fft
+        let _ = self.irfft.process_with_scratch(
+            &mut self.complex_input,
+            &mut self.real_output,
+            &mut self.scratch_space,
+        );
+
+        // apply synthesis window and overlap-add
+        let scale = T::one() / FromF64::from_f64(self.fft_size as f64);
+        if let Some(ref window) = self.window {
+            for (acc, window_acc, src, window_elem) in izip!(
+                self.overlap_buffer.iter_mut(),
+                self.window_sum_buffer.iter_mut(),
+                self.real_output.iter(),
+                window.iter()
+            ) {
+                *acc = *acc + *src * scale * *window_elem;
+                *window_acc = *window_acc + *window_elem * *window_elem;
+            }
+        } else {
+            for (acc, window_acc, src) in izip!(
+                self.overlap_buffer.iter_mut(),
+                self.window_sum_buffer.iter_mut(),
+                self.real_output.iter()
+            ) {
+                *acc = *acc + *src * scale;
+                *window_acc = *window_acc + T::one();
+            }
+        }
+
+        // the first step_size samples are complete now
+        self.normalize_into(output);
+
+        // shift accumulators by step_size
+        self.overlap_buffer.rotate_left(self.step_size);
+        self.window_sum_buffer.rotate_left(self.step_size);
+        let window_size = self.window_size;
+        for elem in self.overlap_buffer[window_size - self.step_size..].iter_mut() {
+            *elem = T::zero();
+        }
+        for elem in self.window_sum_buffer[window_size - self.step_size..].iter_mut() {
+            *elem = T::zero();
+        }
+    }
+
+    /// resynthesizes the rest of the last column's window at the end of the signal
+    /// and resets the accumulators for a new signal
+    /// # Panics
+    /// panics unless `self.flush_size() == output.len()`
+    pub fn flush(&mut self, output: &mut [T]) {
+        assert_eq!(self.flush_size(), output.len());
+        self.normalize_into(output);
+        for elem in self.overlap_buffer.iter_mut() {
+            *elem = T::zero();
+        }
+        for elem in self.window_sum_buffer.iter_mut() {
+            *elem = T::zero();
+        }
+    }
+
+    /// the first `output.len()` overlap-added samples divided by their window sum
+    fn normalize_into(&self, output: &mut [T]) {
+        let epsilon: T = FromF64::from_f64(1e-10);
+        for (dst, acc, window_acc) in izip!(
+            output.iter_mut(),
+            self.overlap_buffer.iter(),
+            self.window_sum_buffer.iter()
+        ) {
+            *dst = if *window_acc > epsilon {
+                *acc / *window_acc
+            } else {
+                T::zero()
+            };
         }
     }
 }
@@ -245,3 +1450,95 @@ impl FromF64 for f32 {
         n as f32
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn istft_reconstructs_the_signal() {
+        let (window_size, step_size) = (1024, 256);
+        let signal: Vec<f32> = (0..8192)
+            .map(|index| {
+                let time = index as f32 / 48000.0;
+                (2.0 * std::f32::consts::PI * 440.0 * time).sin()
+                    + 0.5 * (2.0 * std::f32::consts::PI * 3150.0 * time).cos()
+            })
+            .collect();
+        let spectrogram = calculate_complex_stft(&signal, window_size, step_size);
+        let resynthesized = calculate_istft(&spectrogram, window_size, step_size);
+        let covered = (spectrogram.len() - 1) * step_size + window_size;
+        assert_eq!(resynthesized.len(), covered);
+        // the first samples of the hanning window are too close to 0
+        for (index, (original, output)) in signal[..covered]
+            .iter()
+            .zip(resynthesized.iter())
+            .enumerate()
+            .skip(step_size)
+            .take(covered - 2 * step_size)
+        {
+            assert!(
+                (original - output).abs() < 1e-4,
+                "sample {index}: {original} != {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn reassignment_concentrates_an_off_bin_sinusoid() {
+        let (window_size, step_size, sample_rate) = (1024, 256, 48000.0);
+        // between bin 100 and 101, nearer to 101
+        let bin = 100.7;
+        let frequency = bin * sample_rate / window_size as f64;
+        let signal: Vec<f32> = (0..8 * window_size)
+            .map(|index| {
+                (2.0 * std::f64::consts::PI * frequency * index as f64 / sample_rate).sin() as f32
+            })
+            .collect();
+        let peak_power_ratio = |mode: SpectrogramMode| {
+            let mut stft = STFT::<f32>::new(WindowType::Hanning, window_size, step_size);
+            stft.set_scaling(ScalingMode::Linear);
+            stft.set_mode(mode);
+            let mut column = vec![0.0; stft.output_size()];
+            for window in signal.windows(window_size).step_by(step_size) {
+                stft.compute_column(window, &mut column);
+            }
+            let power: Vec<f32> = column
+                .iter()
+                .map(|magnitude| magnitude * magnitude)
+                .collect();
+            let (peak, peak_power) =
+                power
+                    .iter()
+                    .enumerate()
+                    .fold(
+                        (0, 0.0),
+                        |peak, (bin, &power)| {
+                            if power > peak.1 {
+                                (bin, power)
+                            } else {
+                                peak
+                            }
+                        },
+                    );
+            (peak, peak_power / power.iter().sum::<f32>())
+        };
+        let (standard_peak, standard_ratio) = peak_power_ratio(SpectrogramMode::Standard);
+        let (reassigned_peak, reassigned_ratio) = peak_power_ratio(SpectrogramMode::Reassigned);
+        assert_eq!(standard_peak, 101);
+        assert_eq!(reassigned_peak, 101);
+        assert!(
+            reassigned_ratio > 0.99 && standard_ratio < 0.7,
+            "peak power ratio {reassigned_ratio}, standard {standard_ratio}"
+        );
+    }
+
+    #[test]
+    fn hanning_cola_at_half_and_three_quarter_overlap() {
+        // analysis * synthesis window is hanning squared,
+        // it overlap-adds to a constant at 75% but not at 50% overlap
+        assert!(ISTFT::<f32>::is_cola(WindowType::Hanning, 1024, 256));
+        assert!(!ISTFT::<f32>::is_cola(WindowType::Hanning, 1024, 512));
+        assert!(ISTFT::<f32>::is_cola(WindowType::None, 1024, 512));
+    }
+}
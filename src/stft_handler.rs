@@ -1,100 +1,1236 @@
-use crate::fifo_queue::FifoQueue;
-use crate::spectrum_queue::SpectrumQueue;
+use crate::broadcast::{Broadcast, Subscriber, Subscriptions};
+use crate::chroma::Chroma;
+use crate::cqt::CQT;
+use crate::fifo_queue::{FifoConsumer, FifoProducer, OverflowPolicy};
+use crate::mel::{MelFilterbank, MFCC};
+use crate::spsc_queue::{spsc_queue, SpscConsumer, SpscProducer};
+use crate::stft::FftMode;
+use crate::stft::ScalingMode;
+use crate::stft::SpectrogramMode;
+use crate::stft::WindowNormalization;
 use crate::stft::WindowType;
 use crate::stft::STFT;
+use crate::stream_stats::{StreamStats, StreamTime, Timestamp};
+use num::complex::Complex;
 use ringbuf::Consumer;
+use ringbuf::Producer;
 use ringbuf::SharedRb;
 use std::mem::MaybeUninit;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
 
-type ConsumerRbf32 = Consumer<f32, Arc<SharedRb<f32, std::vec::Vec<MaybeUninit<f32>>>>>;
+pub type ConsumerRbf32 = Consumer<f32, Arc<SharedRb<f32, std::vec::Vec<MaybeUninit<f32>>>>>;
+pub type ProducerRbf32 = Producer<f32, Arc<SharedRb<f32, std::vec::Vec<MaybeUninit<f32>>>>>;
 
-enum FftMode {
-    RustFFT,
-    RealFFT,
+/// column of a `StftHandler` queue with the time of its first sample
+pub type TimedColumn<T = f32> = (Timestamp, Vec<T>);
+
+/// copies the oldest `window.len()` samples of `ringbuffer_out` into `window`
+pub(crate) fn copy_window(ringbuffer_out: &ConsumerRbf32, window: &mut [f32]) {
+    let window_size = window.len();
+    let (older_audio, newer_audio) = ringbuffer_out.as_slices();
+    if older_audio.len() >= window_size {
+        window.copy_from_slice(&older_audio[..window_size]);
+    } else {
+        window[..older_audio.len()].copy_from_slice(older_audio);
+        window[older_audio.len()..]
+            .copy_from_slice(&newer_audio[..window_size - older_audio.len()]);
+    }
 }
 
-pub struct StftHandler {
-    ringbuffer_out: Option<ConsumerRbf32>,
-    spectrum_queue: Arc<Mutex<SpectrumQueue>>,
-    window_size: usize,
-    step_size: usize,
-    time: f32,
-    stft: STFT<f32>,
-    fft_mode: FftMode,
+/// which columns `StftHandler::run` pushes into its queues
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OutputMode {
+    /// scaled magnitude columns, read with `get_spectrum`
+    #[default]
+    Magnitude,
+    /// complex columns with `fft_size / 2 + 1` bins, read with `get_complex_spectrum`,
+    /// magnitude and phase are `norm()` and `arg()` of each bin
+    Complex,
+    /// both of the above
+    MagnitudeAndComplex,
 }
 
-impl Default for StftHandler {
+/// features computed from the linear stft magnitudes, read with `get_features`
+pub enum FeatureMode {
+    /// mel band energies
+    Mel(MelFilterbank<f32>),
+    /// mfccs of the mel band energies
+    Mfcc(MelFilterbank<f32>, MFCC<f32>),
+    /// pitch class profile
+    Chroma(Chroma<f32>),
+}
+
+impl FeatureMode {
+    /// number of values per feature column
+    pub fn output_size(&self) -> usize {
+        match self {
+            FeatureMode::Mel(filterbank) => filterbank.output_size(),
+            FeatureMode::Mfcc(_, mfcc) => mfcc.output_size(),
+            FeatureMode::Chroma(chroma) => chroma.output_size(),
+        }
+    }
+
+    /// adapts the features to columns of an stft with `fft_size` of a signal at `sample_rate`,
+    /// mel bands are cut at the nyquist frequency.
+    /// fails and keeps the features if the mel bands are above the nyquist frequency
+    pub fn rebuild(&mut self, sample_rate: f32, fft_size: usize) -> Result<(), &'static str> {
+        match self {
+            FeatureMode::Mel(filterbank) | FeatureMode::Mfcc(filterbank, _) => {
+                let max_frequency = filterbank.max_frequency.min(sample_rate / 2.0);
+                if filterbank.min_frequency >= max_frequency {
+                    return Err("mel bands are above the nyquist frequency");
+                }
+                *filterbank = MelFilterbank::new(
+                    filterbank.band_count,
+                    filterbank.min_frequency,
+                    max_frequency,
+                    sample_rate,
+                    fft_size,
+                    filterbank.scale,
+                );
+            }
+            FeatureMode::Chroma(chroma) => {
+                chroma.sample_rate = sample_rate;
+                chroma.fft_size = fft_size;
+                // the tuning is kept, the bin map is rebuilt with it
+                chroma.set_tuning(chroma.tuning);
+            }
+        }
+        Ok(())
+    }
+
+    /// computes a feature column of linear `magnitudes`
+    pub fn compute(&mut self, magnitudes: &[f32], output: &mut [f32]) {
+        self.compute_with_buffer(magnitudes, &mut Vec::new(), output);
+    }
+
+    /// `compute` with a reused buffer of the mel energies of mfccs,
+    /// it is resized if needed
+    fn compute_with_buffer(
+        &mut self,
+        magnitudes: &[f32],
+        mel_energies: &mut Vec<f32>,
+        output: &mut [f32],
+    ) {
+        match self {
+            FeatureMode::Mel(filterbank) => filterbank.compute(magnitudes, output),
+            FeatureMode::Mfcc(filterbank, mfcc) => {
+                mel_energies.resize(filterbank.output_size(), 0.0);
+                filterbank.compute(magnitudes, mel_energies);
+                mfcc.compute(mel_energies, output);
+            }
+            FeatureMode::Chroma(chroma) => chroma.compute(magnitudes, output),
+        }
+    }
+}
+
+/// configuration of the `STFT` of a `StftHandler`,
+/// used with `StftHandler::new_with_config` and `StftHandler::reconfigure`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StftConfig {
+    pub window_type: WindowType,
+    pub window_size: usize,
+    /// at least `window_size`, the window is zero padded to this size
+    pub fft_size: usize,
+    /// hop between two columns, at most `window_size`
+    pub step_size: usize,
+    pub scaling: ScalingMode,
+    pub normalization: WindowNormalization,
+    pub include_nyquist: bool,
+    pub mode: SpectrogramMode,
+    pub fft_mode: FftMode,
+}
+
+impl Default for StftConfig {
     fn default() -> Self {
         Self {
-            ringbuffer_out: None,
-            spectrum_queue: Arc::new(Mutex::new(SpectrumQueue::new(2048))),
+            window_type: WindowType::Hanning,
             window_size: 512,
+            fft_size: 1024,
             step_size: 256,
-            time: 0.0,
-            stft: STFT::new(WindowType::Hanning, 1024, 1024),
-            fft_mode: FftMode::RustFFT,
+            scaling: ScalingMode::default(),
+            normalization: WindowNormalization::default(),
+            include_nyquist: false,
+            mode: SpectrogramMode::default(),
+            fft_mode: FftMode::default(),
         }
     }
 }
 
-impl StftHandler {
-    // ToDo improove in future
-    pub fn new(ringbuffer_out: ConsumerRbf32) -> Self {
-        Self {
-            ringbuffer_out: Some(ringbuffer_out),
-            spectrum_queue: Arc::new(Mutex::new(SpectrumQueue::new(2048))),
-            window_size: 512,
-            step_size: 256,
-            time: 0.0,
-            stft: STFT::new(WindowType::Hanning, 1024, 512),
-            fft_mode: FftMode::RustFFT,
+impl StftConfig {
+    pub fn with_window_type(mut self, window_type: WindowType) -> Self {
+        self.window_type = window_type;
+        self
+    }
+
+    pub fn with_window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn with_fft_size(mut self, fft_size: usize) -> Self {
+        self.fft_size = fft_size;
+        self
+    }
+
+    pub fn with_step_size(mut self, step_size: usize) -> Self {
+        self.step_size = step_size;
+        self
+    }
+
+    pub fn with_scaling(mut self, scaling: ScalingMode) -> Self {
+        self.scaling = scaling;
+        self
+    }
+
+    pub fn with_normalization(mut self, normalization: WindowNormalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    pub fn with_include_nyquist(mut self, include_nyquist: bool) -> Self {
+        self.include_nyquist = include_nyquist;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: SpectrogramMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_fft_mode(mut self, fft_mode: FftMode) -> Self {
+        self.fft_mode = fft_mode;
+        self
+    }
+
+    /// checks the sizes, `STFT` panics on invalid sizes,
+    /// and the scaling parameters
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.window_size < 2 {
+            return Err("window size has to be at least 2");
+        }
+        if self.fft_size < self.window_size {
+            return Err("fft size has to be at least the window size");
+        }
+        if self.step_size == 0 || self.step_size > self.window_size {
+            return Err("step size has to be between 1 and the window size");
+        }
+        self.scaling.validate()
+    }
+
+    /// creates the `STFT` described by this config
+    /// # Panics
+    /// panics unless `self.validate()` is ok
+    pub fn build(&self) -> STFT<f32> {
+        if let Err(message) = self.validate() {
+            panic!("{}", message);
+        }
+        let mut stft = STFT::new_with_fft_size(
+            self.window_type,
+            self.window_size,
+            self.fft_size,
+            self.step_size,
+        );
+        stft.set_scaling(self.scaling);
+        stft.set_normalization(self.normalization);
+        stft.set_include_nyquist(self.include_nyquist);
+        stft.set_mode(self.mode);
+        stft.set_fft_mode(self.fft_mode);
+        stft
+    }
+}
+
+/// processing state of a `StftHandler`, shared with its worker thread.
+/// reached with `StftHandler::processor`
+pub struct StftProcessor {
+    ringbuffer_out: Option<ConsumerRbf32>,
+    /// imaginary part of complex input, `ringbuffer_out` holds the real part
+    ringbuffer_q_out: Option<ConsumerRbf32>,
+    spectrum_queue: SpscProducer<TimedColumn>,
+    complex_queue: SpscProducer<TimedColumn<Complex<f32>>>,
+    output_mode: OutputMode,
+    features: Option<FeatureMode>,
+    feature_queue: SpscProducer<TimedColumn>,
+    /// copies of the queued columns for further consumers
+    spectrum_broadcast: Broadcast<TimedColumn>,
+    complex_broadcast: Broadcast<TimedColumn<Complex<f32>>>,
+    feature_broadcast: Broadcast<TimedColumn>,
+    /// index of the next sample read from the ringbuffer
+    sample_index: u64,
+    stream_stats: Arc<StreamStats>,
+    sample_rate: f32,
+    config: StftConfig,
+    stft: STFT<f32>,
+    /// replaces the stft if set
+    cqt: Option<CQT<f32>>,
+    /// buffers reused by `run`, resized if the sizes change
+    window_buffer: Vec<f32>,
+    quadrature_buffer: Vec<f32>,
+    complex_input: Vec<Complex<f32>>,
+    complex_buffer: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    mel_energies: Vec<f32>,
+    /// copy of the settings for the getters of `StftHandler`
+    settings: Arc<Mutex<Settings>>,
+}
+
+/// bins of a constant-Q transform
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct ConstantQBins {
+    min_frequency: f32,
+    bins_per_octave: usize,
+    bin_count: usize,
+}
+
+/// copy of the settings of a `StftProcessor`, updated by its setters.
+/// read by the getters of `StftHandler`, which do not wait for the worker thread
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+struct Settings {
+    config: StftConfig,
+    is_iq: bool,
+    output_size: usize,
+    step_size: usize,
+    sample_rate: f32,
+    output_mode: OutputMode,
+    overflow_policy: OverflowPolicy,
+    constant_q: Option<ConstantQBins>,
+    /// values per feature column, 0 without features
+    feature_size: usize,
+    /// bins per octave of `FeatureMode::Chroma`
+    chroma_bins: Option<usize>,
+}
+
+impl Settings {
+    /// see `StftProcessor::bin_frequency`
+    fn bin_frequency(&self, bin: usize) -> f32 {
+        let fft_size = self.config.fft_size;
+        match self.constant_q {
+            _ if self.is_iq => {
+                (bin as f32 - (fft_size / 2) as f32) * self.sample_rate / fft_size as f32
+            }
+            Some(bins) => {
+                bins.min_frequency * 2.0f32.powf(bin as f32 / bins.bins_per_octave as f32)
+            }
+            None => bin as f32 * self.sample_rate / fft_size as f32,
+        }
+    }
+
+    /// see `StftProcessor::frequency_bin`
+    fn frequency_bin(&self, frequency: f32) -> usize {
+        let fft_size = self.config.fft_size;
+        match self.constant_q {
+            _ if self.is_iq => {
+                let bin = (frequency * fft_size as f32 / self.sample_rate).round()
+                    + (fft_size / 2) as f32;
+                (bin.max(0.0) as usize).min(fft_size - 1)
+            }
+            Some(bins) => {
+                let bin =
+                    (bins.bins_per_octave as f32 * (frequency / bins.min_frequency).log2()).round();
+                (bin.max(0.0) as usize).min(bins.bin_count - 1)
+            }
+            None => {
+                let bin = (frequency * fft_size as f32 / self.sample_rate).round();
+                (bin.max(0.0) as usize).min(fft_size / 2)
+            }
+        }
+    }
+}
+
+/// queues `column` and publishes a copy if anyone subscribed to `broadcast`
+fn push_column<U: Clone>(
+    queue: &mut SpscProducer<TimedColumn<U>>,
+    broadcast: &mut Broadcast<TimedColumn<U>>,
+    timestamp: Timestamp,
+    column: Vec<U>,
+) {
+    broadcast.publish_with(|| (timestamp, column.clone()));
+    queue.push((timestamp, column));
+}
+
+/// a column of `size` values from the recycled columns of `queue`,
+/// allocated only if none is left
+pub(crate) fn recycled_column<U: Clone>(
+    queue: &mut SpscProducer<TimedColumn<U>>,
+    size: usize,
+    fill: U,
+) -> Vec<U> {
+    let mut column = queue
+        .take_recycled()
+        .map(|(_, column)| column)
+        .unwrap_or_default();
+    column.clear();
+    column.resize(size, fill);
+    column
+}
+
+/// time of the sample at `sample_index`, `buffered` samples are left in the ringbuffer from it on.
+/// without a `StreamTime` of the producer the newest sample is taken as arrived just now
+pub(crate) fn column_timestamp(
+    stream_time: Option<StreamTime>,
+    sample_index: u64,
+    buffered: usize,
+    sample_rate: f32,
+) -> Timestamp {
+    match stream_time {
+        Some(stream_time) => stream_time.timestamp(sample_index, sample_rate),
+        None => Timestamp {
+            sample_index,
+            frame_time: None,
+            wall_time: SystemTime::now()
+                - Duration::from_secs_f64(buffered as f64 / sample_rate as f64),
+        },
+    }
+}
+
+impl StftProcessor {
+    fn new(
+        ringbuffer_out: Option<ConsumerRbf32>,
+        config: StftConfig,
+        spectrum_queue: SpscProducer<TimedColumn>,
+        complex_queue: SpscProducer<TimedColumn<Complex<f32>>>,
+        feature_queue: SpscProducer<TimedColumn>,
+    ) -> Self {
+        let processor = Self {
+            ringbuffer_out,
+            ringbuffer_q_out: None,
+            spectrum_queue,
+            complex_queue,
+            output_mode: OutputMode::Magnitude,
+            features: None,
+            feature_queue,
+            spectrum_broadcast: Broadcast::new(),
+            complex_broadcast: Broadcast::new(),
+            feature_broadcast: Broadcast::new(),
+            sample_index: 0,
+            stream_stats: Arc::new(StreamStats::new()),
+            sample_rate: 48000.0,
+            config,
+            stft: config.build(),
+            cqt: None,
+            window_buffer: Vec::new(),
+            quadrature_buffer: Vec::new(),
+            complex_input: Vec::new(),
+            complex_buffer: Vec::new(),
+            magnitudes: Vec::new(),
+            mel_energies: Vec::new(),
+            settings: Arc::default(),
+        };
+        processor.update_settings();
+        processor
+    }
+
+    /// copies the settings for the getters of `StftHandler`, called by every setter
+    fn update_settings(&self) {
+        let settings = Settings {
+            config: self.config,
+            is_iq: self.is_iq(),
+            output_size: self.output_size(),
+            step_size: self.step_size(),
+            sample_rate: self.sample_rate,
+            output_mode: self.output_mode,
+            overflow_policy: self.overflow_policy(),
+            constant_q: self.cqt.as_ref().map(|cqt| ConstantQBins {
+                min_frequency: cqt.min_frequency,
+                bins_per_octave: cqt.bins_per_octave,
+                bin_count: cqt.bin_count,
+            }),
+            feature_size: self.features.as_ref().map_or(0, FeatureMode::output_size),
+            chroma_bins: match &self.features {
+                Some(FeatureMode::Chroma(chroma)) => Some(chroma.bins_per_octave),
+                _ => None,
+            },
+        };
+        *self.settings.lock().expect("Unlock") = settings;
+    }
+
+    pub fn config(&self) -> StftConfig {
+        self.config
+    }
+
+    /// rebuilds the stft from `config`, audio in the ringbuffer and
+    /// columns in the queues are kept.
+    /// features are rebuilt for the new fft size
+    pub fn reconfigure(&mut self, config: StftConfig) -> Result<(), &'static str> {
+        config.validate()?;
+        if config.fft_size != self.config.fft_size {
+            if let Some(features) = &mut self.features {
+                features.rebuild(self.sample_rate, config.fft_size)?;
+            }
+        }
+        self.stft = config.build();
+        if let Some(cqt) = &mut self.cqt {
+            cqt.set_scaling(config.scaling);
         }
+        self.config = config;
+        self.update_settings();
+        Ok(())
+    }
+
+    /// analyzes complex (analytic or IQ) input: `ringbuffer_out` holds the real (I)
+    /// and `ringbuffer_q_out` the imaginary (Q) part, `None` switches back to real input.
+    /// complex input always uses rustfft and gives `fft_size` bins from `-fs / 2` to `fs / 2`,
+    /// constant-Q transform, reassignment and features are not applied
+    pub fn set_quadrature_input(&mut self, ringbuffer_q_out: Option<ConsumerRbf32>) {
+        self.ringbuffer_q_out = ringbuffer_q_out;
+        self.update_settings();
+    }
+
+    /// true if complex input is analyzed
+    pub fn is_iq(&self) -> bool {
+        self.ringbuffer_q_out.is_some()
+    }
+
+    pub fn fft_mode(&self) -> FftMode {
+        self.config.fft_mode
+    }
+
+    /// selects realfft or rustfft for real input
+    pub fn set_fft_mode(&mut self, fft_mode: FftMode) {
+        self.config.fft_mode = fft_mode;
+        self.stft.set_fft_mode(fft_mode);
+        self.update_settings();
     }
 
     pub fn run(&mut self) {
-        match &mut self.ringbuffer_out {
-            Some(ringbuffer_out) => {
-                while ringbuffer_out.len() > 512 {
-                    let mut values: Vec<f32> = vec![0.0; 512];
-                    let mut tmp_vec: Vec<f32> = vec![0.0; 1024];
-                    if ringbuffer_out.len() >= self.window_size {
-                        let (older_audio, newer_audio) = ringbuffer_out.as_slices();
-                        if older_audio.len() >= self.window_size {
-                            tmp_vec[..self.window_size]
-                                .copy_from_slice(&older_audio[..self.window_size]);
-                        } else {
-                            tmp_vec[..older_audio.len()].copy_from_slice(&older_audio[..]);
-                            tmp_vec[older_audio.len()..self.window_size].copy_from_slice(
-                                &newer_audio[..self.window_size - older_audio.len()],
-                            );
-                        }
-                        ringbuffer_out.skip(self.step_size);
-                        match self.fft_mode {
-                            FftMode::RustFFT => {
-                                self.stft.compute_column(&mut tmp_vec, &mut values);
-                            }
-                            FftMode::RealFFT => {
-                                // dummy implementation
-                                // ToDo call RealFFT
-                                self.stft.compute_column(&mut tmp_vec, &mut values);
-                            }
-                        }
-
-                        self.spectrum_queue.lock().expect("Unlock").push(values);
+        if self.is_iq() {
+            self.run_iq();
+            return;
+        }
+        let (window_size, step_size, complex_output_size) = match &self.cqt {
+            Some(cqt) => (cqt.window_size(), cqt.step_size, cqt.output_size()),
+            None => (
+                self.stft.window_size,
+                self.stft.step_size,
+                self.stft.complex_output_size(),
+            ),
+        };
+        let output_size = self.output_size();
+        self.window_buffer.resize(window_size, 0.0);
+        self.complex_buffer
+            .resize(complex_output_size, Complex::new(0.0, 0.0));
+        let stream_time = self.stream_stats.time();
+        // taken while the loop asks the queues if they are blocked
+        let Some(mut ringbuffer_out) = self.ringbuffer_out.take() else {
+            return;
+        };
+        while ringbuffer_out.len() >= window_size && !self.is_blocked() {
+            let timestamp = column_timestamp(
+                stream_time,
+                self.sample_index,
+                ringbuffer_out.len(),
+                self.sample_rate,
+            );
+            copy_window(&ringbuffer_out, &mut self.window_buffer);
+            ringbuffer_out.skip(step_size);
+            self.sample_index += step_size as u64;
+            if let Some(cqt) = &mut self.cqt {
+                cqt.compute_into_complex_output(&self.window_buffer, &mut self.complex_buffer);
+                if self.output_mode != OutputMode::Complex {
+                    let mut values = recycled_column(&mut self.spectrum_queue, output_size, 0.0);
+                    cqt.complex_to_column(&self.complex_buffer, &mut values);
+                    push_column(
+                        &mut self.spectrum_queue,
+                        &mut self.spectrum_broadcast,
+                        timestamp,
+                        values,
+                    );
+                }
+                if self.output_mode != OutputMode::Magnitude {
+                    Self::push_complex(
+                        &mut self.complex_queue,
+                        &mut self.complex_broadcast,
+                        timestamp,
+                        &self.complex_buffer,
+                    );
+                }
+                continue;
+            }
+            // realfft or rustfft depending on the stft's fft mode
+            self.stft
+                .compute_into_complex_output(&self.window_buffer, &mut self.complex_buffer);
+
+            if self.output_mode != OutputMode::Complex {
+                let mut values = recycled_column(&mut self.spectrum_queue, output_size, 0.0);
+                let column_timestamp = match self.stft.mode {
+                    SpectrogramMode::Standard => {
+                        self.stft
+                            .complex_to_column(&self.complex_buffer, &mut values);
+                        timestamp
                     }
+                    SpectrogramMode::Reassigned => {
+                        self.stft.reassign_column(
+                            &self.window_buffer,
+                            &self.complex_buffer,
+                            &mut values,
+                        );
+                        // the reassigned column belongs to an earlier window
+                        let delay = self.stft.reassignment_delay() * step_size;
+                        timestamp.earlier(delay as u64, self.sample_rate)
+                    }
+                };
+                push_column(
+                    &mut self.spectrum_queue,
+                    &mut self.spectrum_broadcast,
+                    column_timestamp,
+                    values,
+                );
+            }
+            if let Some(features) = &mut self.features {
+                self.magnitudes.resize(complex_output_size, 0.0);
+                self.stft
+                    .complex_to_magnitudes(&self.complex_buffer, &mut self.magnitudes);
+                let mut feature_values =
+                    recycled_column(&mut self.feature_queue, features.output_size(), 0.0);
+                features.compute_with_buffer(
+                    &self.magnitudes,
+                    &mut self.mel_energies,
+                    &mut feature_values,
+                );
+                push_column(
+                    &mut self.feature_queue,
+                    &mut self.feature_broadcast,
+                    timestamp,
+                    feature_values,
+                );
+            }
+            if self.output_mode != OutputMode::Magnitude {
+                Self::push_complex(
+                    &mut self.complex_queue,
+                    &mut self.complex_broadcast,
+                    timestamp,
+                    &self.complex_buffer,
+                );
+            }
+        }
+        self.ringbuffer_out = Some(ringbuffer_out);
+    }
+
+    /// pushes a copy of `complex_values` in a recycled column
+    fn push_complex(
+        complex_queue: &mut SpscProducer<TimedColumn<Complex<f32>>>,
+        complex_broadcast: &mut Broadcast<TimedColumn<Complex<f32>>>,
+        timestamp: Timestamp,
+        complex_values: &[Complex<f32>],
+    ) {
+        let mut column =
+            recycled_column(complex_queue, complex_values.len(), Complex::new(0.0, 0.0));
+        column.copy_from_slice(complex_values);
+        push_column(complex_queue, complex_broadcast, timestamp, column);
+    }
+
+    fn run_iq(&mut self) {
+        let window_size = self.stft.window_size;
+        let step_size = self.stft.step_size;
+        let output_size = self.stft.iq_output_size();
+        // taken while the loop asks the queues if they are blocked
+        let (mut ringbuffer_out, mut ringbuffer_q_out) =
+            match (self.ringbuffer_out.take(), self.ringbuffer_q_out.take()) {
+                (Some(ringbuffer_out), Some(ringbuffer_q_out)) => {
+                    (ringbuffer_out, ringbuffer_q_out)
                 }
+                (ringbuffer_out, ringbuffer_q_out) => {
+                    self.ringbuffer_out = ringbuffer_out;
+                    self.ringbuffer_q_out = ringbuffer_q_out;
+                    return;
+                }
+            };
+        self.window_buffer.resize(window_size, 0.0);
+        self.quadrature_buffer.resize(window_size, 0.0);
+        self.complex_input
+            .resize(window_size, Complex::new(0.0, 0.0));
+        self.complex_buffer
+            .resize(output_size, Complex::new(0.0, 0.0));
+        let stream_time = self.stream_stats.time();
+        while ringbuffer_out.len() >= window_size
+            && ringbuffer_q_out.len() >= window_size
+            && !self.is_blocked()
+        {
+            let timestamp = column_timestamp(
+                stream_time,
+                self.sample_index,
+                ringbuffer_out.len(),
+                self.sample_rate,
+            );
+            copy_window(&ringbuffer_out, &mut self.window_buffer);
+            copy_window(&ringbuffer_q_out, &mut self.quadrature_buffer);
+            ringbuffer_out.skip(step_size);
+            ringbuffer_q_out.skip(step_size);
+            self.sample_index += step_size as u64;
+            for (dst, (&re, &im)) in self
+                .complex_input
+                .iter_mut()
+                .zip(self.window_buffer.iter().zip(self.quadrature_buffer.iter()))
+            {
+                *dst = Complex::new(re, im);
+            }
+            self.stft
+                .compute_iq_into_complex_output(&self.complex_input, &mut self.complex_buffer);
+            if self.output_mode != OutputMode::Complex {
+                let mut values = recycled_column(&mut self.spectrum_queue, output_size, 0.0);
+                self.stft.iq_to_column(&self.complex_buffer, &mut values);
+                push_column(
+                    &mut self.spectrum_queue,
+                    &mut self.spectrum_broadcast,
+                    timestamp,
+                    values,
+                );
+            }
+            if self.output_mode != OutputMode::Magnitude {
+                Self::push_complex(
+                    &mut self.complex_queue,
+                    &mut self.complex_broadcast,
+                    timestamp,
+                    &self.complex_buffer,
+                );
+            }
+        }
+        self.ringbuffer_out = Some(ringbuffer_out);
+        self.ringbuffer_q_out = Some(ringbuffer_q_out);
+    }
+
+    /// true while a queue or subscriber of the next column is full
+    /// and waits for its consumer with `OverflowPolicy::Block`.
+    /// `run` stops there, so a worker waits without holding the processor
+    fn is_blocked(&mut self) -> bool {
+        let magnitudes = self.output_mode != OutputMode::Complex;
+        let complex = self.output_mode != OutputMode::Magnitude;
+        let features = self.features.is_some() && self.cqt.is_none() && !self.is_iq();
+        (magnitudes && (self.spectrum_queue.is_blocked() || self.spectrum_broadcast.is_blocked()))
+            || (complex && (self.complex_queue.is_blocked() || self.complex_broadcast.is_blocked()))
+            || (features
+                && (self.feature_queue.is_blocked() || self.feature_broadcast.is_blocked()))
+    }
+
+    /// number of values per spectrum column, follows the fft size
+    /// or the number of constant-Q bins
+    pub fn output_size(&self) -> usize {
+        if self.is_iq() {
+            return self.stft.iq_output_size();
+        }
+        match &self.cqt {
+            Some(cqt) => cqt.output_size(),
+            None => self.stft.output_size(),
+        }
+    }
+
+    pub fn cqt(&self) -> Option<&CQT<f32>> {
+        self.cqt.as_ref()
+    }
+
+    /// replaces the stft by a constant-Q transform, `None` switches back to the stft.
+    /// the scaling of the handler is applied to the constant-Q transform.
+    /// fails and keeps the current transform if the window of the constant-Q transform
+    /// is larger than the ringbuffer, it would never be filled
+    pub fn set_cqt(&mut self, cqt: Option<CQT<f32>>) -> Result<(), &'static str> {
+        if let (Some(cqt), Some(ringbuffer_out)) = (&cqt, &self.ringbuffer_out) {
+            if cqt.window_size() > ringbuffer_out.capacity() {
+                return Err("constant-Q window is larger than the ringbuffer");
             }
-            None => (),
         }
+        self.cqt = cqt.map(|mut cqt| {
+            cqt.set_scaling(self.stft.scaling);
+            cqt
+        });
+        self.update_settings();
+        Ok(())
+    }
+
+    /// number of samples between two columns
+    pub fn step_size(&self) -> usize {
+        match &self.cqt {
+            Some(cqt) => cqt.step_size,
+            None => self.stft.step_size,
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
     }
 
-    pub fn get_spectrum(&mut self) -> Vec<Vec<f32>> {
-        // ToDo: return tuble
+    /// sample rate of the audio in the ringbuffer, used for bin to frequency mapping
+    /// features are rebuilt for the new sample rate,
+    /// fails and keeps the sample rate if they do not fit, see `FeatureMode::rebuild`
+    pub fn set_sample_rate(&mut self, sample_rate: f32) -> Result<(), &'static str> {
+        if let Some(features) = &mut self.features {
+            features.rebuild(sample_rate, self.stft.fft_size)?;
+        }
+        self.sample_rate = sample_rate;
+        self.update_settings();
+        Ok(())
+    }
+
+    /// center frequency in Hz of `bin`, logarithmic if a constant-Q transform is used,
+    /// negative frequencies first for complex input
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        if self.is_iq() {
+            return self.stft.iq_bin_frequency(bin, self.sample_rate);
+        }
+        match &self.cqt {
+            Some(cqt) => cqt.bin_frequency(bin),
+            None => self.stft.bin_frequency(bin, self.sample_rate),
+        }
+    }
+
+    /// nearest bin of `frequency` in Hz
+    pub fn frequency_bin(&self, frequency: f32) -> usize {
+        if self.is_iq() {
+            return self.stft.iq_frequency_bin(frequency, self.sample_rate);
+        }
+        match &self.cqt {
+            Some(cqt) => cqt.frequency_bin(frequency),
+            None => self.stft.frequency_bin(frequency, self.sample_rate),
+        }
+    }
+
+    pub fn set_include_nyquist(&mut self, include_nyquist: bool) {
+        self.config.include_nyquist = include_nyquist;
+        self.stft.set_include_nyquist(include_nyquist);
+        self.update_settings();
+    }
+
+    pub fn scaling(&self) -> ScalingMode {
+        self.stft.scaling
+    }
+
+    pub fn set_scaling(&mut self, scaling: ScalingMode) {
+        self.config.scaling = scaling;
+        self.stft.set_scaling(scaling);
+        if let Some(cqt) = &mut self.cqt {
+            cqt.set_scaling(scaling);
+        }
+        self.update_settings();
+    }
+
+    pub fn normalization(&self) -> WindowNormalization {
+        self.stft.normalization
+    }
+
+    pub fn set_normalization(&mut self, normalization: WindowNormalization) {
+        self.config.normalization = normalization;
+        self.stft.set_normalization(normalization);
+        self.update_settings();
+    }
+
+    pub fn mode(&self) -> SpectrogramMode {
+        self.stft.mode
+    }
+
+    /// standard or reassigned magnitude columns,
+    /// complex columns are not affected
+    pub fn set_mode(&mut self, mode: SpectrogramMode) {
+        self.config.mode = mode;
+        self.stft.set_mode(mode);
+        self.update_settings();
+    }
+
+    pub fn output_mode(&self) -> OutputMode {
+        self.output_mode
+    }
+
+    pub fn set_output_mode(&mut self, output_mode: OutputMode) {
+        self.output_mode = output_mode;
+        self.update_settings();
+    }
+
+    /// sets the features computed from every stft column,
+    /// filterbanks have to match the stft's fft size and the sample rate.
+    /// no features are computed while a constant-Q transform is used
+    pub fn set_features(&mut self, features: Option<FeatureMode>) {
+        self.features = features;
+        self.update_settings();
+    }
+
+    pub fn features(&self) -> Option<&FeatureMode> {
+        self.features.as_ref()
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.spectrum_queue.policy()
+    }
+
+    /// sets what the spectrum, complex and feature queues do when they are full,
+    /// see `SpscProducer::set_policy`
+    /// # Panics
+    /// panics on `OverflowPolicy::Decimate(0)`
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.spectrum_queue.set_policy(policy);
+        self.complex_queue.set_policy(policy);
+        self.feature_queue.set_policy(policy);
+        self.update_settings();
+    }
+
+    /// changes of the output size are not seen by the getters of `StftHandler`,
+    /// use `set_features` for them
+    pub fn features_mut(&mut self) -> Option<&mut FeatureMode> {
+        self.features.as_mut()
+    }
+
+    /// fft size of the stft, needed to create filterbanks
+    pub fn fft_size(&self) -> usize {
+        self.stft.fft_size
+    }
+}
+
+/// thread running `StftProcessor::run` until `running` is cleared
+struct Worker {
+    running: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// computes spectrogram columns of a ringbuffer with a `StftProcessor`,
+/// either on calls of `run` or continuously in a worker thread started with `start_worker`.
+/// columns are drained from queues, `get_spectrum` does not wait for the processing
+pub struct StftHandler {
+    processor: Arc<Mutex<StftProcessor>>,
+    spectrum_queue: SpscConsumer<TimedColumn>,
+    complex_queue: SpscConsumer<TimedColumn<Complex<f32>>>,
+    feature_queue: SpscConsumer<TimedColumn>,
+    spectrum_subscriptions: Subscriptions<TimedColumn>,
+    complex_subscriptions: Subscriptions<TimedColumn<Complex<f32>>>,
+    feature_subscriptions: Subscriptions<TimedColumn>,
+    worker: Option<Worker>,
+    stream_stats: Arc<StreamStats>,
+    settings: Arc<Mutex<Settings>>,
+}
+
+impl Default for StftHandler {
+    fn default() -> Self {
+        Self::with_config(None, StftConfig::default())
+    }
+}
+
+impl Drop for StftHandler {
+    fn drop(&mut self) {
+        self.stop_worker();
+    }
+}
+
+impl StftHandler {
+    /// time the worker thread sleeps when the ringbuffer holds less than a window
+    pub const WORKER_INTERVAL: Duration = Duration::from_millis(5);
+    /// number of columns kept in each queue until they are drained
+    pub const QUEUE_SIZE: usize = 2048;
+
+    pub fn new(ringbuffer_out: ConsumerRbf32) -> Self {
+        Self::with_config(Some(ringbuffer_out), StftConfig::default())
+    }
+
+    /// # Panics
+    /// panics unless `config.validate()` is ok
+    pub fn new_with_config(ringbuffer_out: ConsumerRbf32, config: StftConfig) -> Self {
+        Self::with_config(Some(ringbuffer_out), config)
+    }
+
+    fn with_config(ringbuffer_out: Option<ConsumerRbf32>, config: StftConfig) -> Self {
+        let (spectrum_in, spectrum_queue) = spsc_queue(Self::QUEUE_SIZE);
+        let (complex_in, complex_queue) = spsc_queue(Self::QUEUE_SIZE);
+        let (feature_in, feature_queue) = spsc_queue(Self::QUEUE_SIZE);
+        let processor =
+            StftProcessor::new(ringbuffer_out, config, spectrum_in, complex_in, feature_in);
+        Self {
+            spectrum_queue,
+            complex_queue,
+            feature_queue,
+            spectrum_subscriptions: processor.spectrum_broadcast.subscriptions(),
+            complex_subscriptions: processor.complex_broadcast.subscriptions(),
+            feature_subscriptions: processor.feature_broadcast.subscriptions(),
+            stream_stats: processor.stream_stats.clone(),
+            settings: processor.settings.clone(),
+            processor: Arc::new(Mutex::new(processor)),
+            worker: None,
+        }
+    }
+
+    /// locks the processing state, waits while the worker thread computes columns
+    pub fn processor(&self) -> MutexGuard<'_, StftProcessor> {
+        self.processor.lock().expect("Unlock")
+    }
+
+    /// copy of the processor's settings, does not wait for the worker thread
+    fn settings(&self) -> Settings {
+        *self.settings.lock().expect("Unlock")
+    }
+
+    /// starts a thread computing columns as soon as the ringbuffer holds a window,
+    /// `run` does not have to be called while it runs
+    pub fn start_worker(&mut self) {
+        if self.worker.is_some() {
+            return;
+        }
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            let processor = self.processor.clone();
+            thread::spawn(move || {
+                while running.load(Ordering::Acquire) {
+                    processor.lock().expect("Unlock").run();
+                    thread::park_timeout(Self::WORKER_INTERVAL);
+                }
+            })
+        };
+        self.worker = Some(Worker { running, thread });
+    }
+
+    /// stops the worker thread and waits for it to finish its pass,
+    /// columns computed so far stay in the queues
+    pub fn stop_worker(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            worker.running.store(false, Ordering::Release);
+            worker.thread.thread().unpark();
+            // a panic of the worker poisons the processor and shows up on the next lock
+            let _ = worker.thread.join();
+        }
+    }
+
+    pub fn has_worker(&self) -> bool {
+        self.worker.is_some()
+    }
+
+    /// computes all columns of the audio in the ringbuffer,
+    /// not needed while a worker thread runs.
+    /// stops early while a queue with `OverflowPolicy::Block` is full,
+    /// the rest of the audio is left for the next call
+    pub fn run(&mut self) {
+        self.processor().run();
+    }
+
+    pub fn config(&self) -> StftConfig {
+        self.settings().config
+    }
+
+    /// see `StftProcessor::reconfigure`
+    pub fn reconfigure(&mut self, config: StftConfig) -> Result<(), &'static str> {
+        self.processor().reconfigure(config)
+    }
+
+    /// see `StftProcessor::set_quadrature_input`
+    pub fn set_quadrature_input(&mut self, ringbuffer_q_out: Option<ConsumerRbf32>) {
+        self.processor().set_quadrature_input(ringbuffer_q_out);
+    }
+
+    pub fn is_iq(&self) -> bool {
+        self.settings().is_iq
+    }
+
+    pub fn fft_mode(&self) -> FftMode {
+        self.settings().config.fft_mode
+    }
+
+    pub fn set_fft_mode(&mut self, fft_mode: FftMode) {
+        self.processor().set_fft_mode(fft_mode);
+    }
+
+    pub fn output_size(&self) -> usize {
+        self.settings().output_size
+    }
+
+    /// see `StftProcessor::set_cqt`
+    pub fn set_cqt(&mut self, cqt: Option<CQT<f32>>) -> Result<(), &'static str> {
+        self.processor().set_cqt(cqt)
+    }
+
+    pub fn step_size(&self) -> usize {
+        self.settings().step_size
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.settings().sample_rate
+    }
+
+    /// see `StftProcessor::set_sample_rate`
+    pub fn set_sample_rate(&mut self, sample_rate: f32) -> Result<(), &'static str> {
+        self.processor().set_sample_rate(sample_rate)
+    }
+
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        self.settings().bin_frequency(bin)
+    }
+
+    pub fn frequency_bin(&self, frequency: f32) -> usize {
+        self.settings().frequency_bin(frequency)
+    }
+
+    pub fn set_include_nyquist(&mut self, include_nyquist: bool) {
+        self.processor().set_include_nyquist(include_nyquist);
+    }
+
+    pub fn scaling(&self) -> ScalingMode {
+        self.settings().config.scaling
+    }
+
+    pub fn set_scaling(&mut self, scaling: ScalingMode) {
+        self.processor().set_scaling(scaling);
+    }
+
+    pub fn normalization(&self) -> WindowNormalization {
+        self.settings().config.normalization
+    }
+
+    pub fn set_normalization(&mut self, normalization: WindowNormalization) {
+        self.processor().set_normalization(normalization);
+    }
+
+    pub fn mode(&self) -> SpectrogramMode {
+        self.settings().config.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SpectrogramMode) {
+        self.processor().set_mode(mode);
+    }
+
+    pub fn output_mode(&self) -> OutputMode {
+        self.settings().output_mode
+    }
+
+    pub fn set_output_mode(&mut self, output_mode: OutputMode) {
+        self.processor().set_output_mode(output_mode);
+    }
+
+    /// see `StftProcessor::set_features`
+    pub fn set_features(&mut self, features: Option<FeatureMode>) {
+        self.processor().set_features(features);
+    }
+
+    /// values per feature column, 0 without features
+    pub fn feature_size(&self) -> usize {
+        self.settings().feature_size
+    }
+
+    /// bins per octave if the features are a `FeatureMode::Chroma`
+    pub fn chroma_bins(&self) -> Option<usize> {
+        self.settings().chroma_bins
+    }
+
+    /// true if a constant-Q transform replaces the stft
+    pub fn has_cqt(&self) -> bool {
+        self.settings().constant_q.is_some()
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.settings().overflow_policy
+    }
+
+    /// see `StftProcessor::set_overflow_policy`,
+    /// `OverflowPolicy::Block` needs the worker thread
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.processor().set_overflow_policy(policy);
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.settings().config.fft_size
+    }
+
+    /// counters and time of the ringbuffer feeding this handler,
+    /// to be updated by whoever pushes into it, e.g. the jack process callback
+    pub fn stream_stats(&self) -> Arc<StreamStats> {
+        self.stream_stats.clone()
+    }
+
+    /// shares counters with other handlers fed by the same source
+    pub fn set_stream_stats(&mut self, stream_stats: Arc<StreamStats>) {
+        self.processor().stream_stats = stream_stats.clone();
+        self.stream_stats = stream_stats;
+    }
+
+    /// samples which did not fit into a full ringbuffer
+    pub fn dropped_samples(&self) -> usize {
+        self.stream_stats.dropped_samples()
+    }
+
+    /// buffer under or overruns of the jack server
+    pub fn xruns(&self) -> usize {
+        self.stream_stats.xruns()
+    }
+
+    /// columns dropped by full queues because they were not drained in time
+    pub fn dropped_columns(&self) -> usize {
+        self.spectrum_queue.dropped() + self.complex_queue.dropped() + self.feature_queue.dropped()
+    }
+
+    /// an independent consumer of the magnitude columns besides `get_spectrum`,
+    /// keeping up to `size` columns with its own `policy` and dropped count.
+    /// `OverflowPolicy::Block` holds up the processing, i.e. needs the worker thread
+    /// # Panics
+    /// panics if `size` is 0 or on `OverflowPolicy::Decimate(0)`
+    pub fn subscribe_spectrum(
+        &self,
+        size: usize,
+        policy: OverflowPolicy,
+    ) -> Subscriber<TimedColumn> {
+        self.spectrum_subscriptions.subscribe(size, policy)
+    }
+
+    /// see `subscribe_spectrum`, only filled if `output_mode` includes complex columns
+    /// # Panics
+    /// panics if `size` is 0 or on `OverflowPolicy::Decimate(0)`
+    pub fn subscribe_complex_spectrum(
+        &self,
+        size: usize,
+        policy: OverflowPolicy,
+    ) -> Subscriber<TimedColumn<Complex<f32>>> {
+        self.complex_subscriptions.subscribe(size, policy)
+    }
+
+    /// see `subscribe_spectrum`, only filled if features are set
+    /// # Panics
+    /// panics if `size` is 0 or on `OverflowPolicy::Decimate(0)`
+    pub fn subscribe_features(
+        &self,
+        size: usize,
+        policy: OverflowPolicy,
+    ) -> Subscriber<TimedColumn> {
+        self.feature_subscriptions.subscribe(size, policy)
+    }
+
+    /// drains the magnitude columns with their timestamps
+    pub fn get_spectrum(&mut self) -> Vec<TimedColumn> {
         let mut spec_vec = Vec::new();
-        while let Some(spectrum) = self.spectrum_queue.lock().expect("Unlock").pop() {
+        while let Some(spectrum) = self.spectrum_queue.pop() {
             spec_vec.push(spectrum);
         }
-        return spec_vec;
+        spec_vec
+    }
+
+    /// hands columns of `get_spectrum` back to be reused by `run`
+    pub fn recycle_spectrum(&mut self, columns: Vec<Vec<f32>>) {
+        for column in columns {
+            self.spectrum_queue.recycle((Timestamp::default(), column));
+        }
+    }
+
+    /// drains the feature columns, only filled if features are set
+    pub fn get_features(&mut self) -> Vec<TimedColumn> {
+        let mut feature_vec = Vec::new();
+        while let Some(features) = self.feature_queue.pop() {
+            feature_vec.push(features);
+        }
+        feature_vec
+    }
+
+    /// hands columns of `get_features` back to be reused by `run`
+    pub fn recycle_features(&mut self, columns: Vec<Vec<f32>>) {
+        for column in columns {
+            self.feature_queue.recycle((Timestamp::default(), column));
+        }
+    }
+
+    /// drains the complex columns, only filled if `output_mode` includes them
+    pub fn get_complex_spectrum(&mut self) -> Vec<TimedColumn<Complex<f32>>> {
+        let mut spec_vec = Vec::new();
+        while let Some(spectrum) = self.complex_queue.pop() {
+            spec_vec.push(spectrum);
+        }
+        spec_vec
+    }
+
+    /// hands columns of `get_complex_spectrum` back to be reused by `run`
+    pub fn recycle_complex_spectrum(&mut self, columns: Vec<Vec<Complex<f32>>>) {
+        for column in columns {
+            self.complex_queue.recycle((Timestamp::default(), column));
+        }
     }
 }
@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// time of a spectrum column, taken at the first sample of its window
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Timestamp {
+    /// index of the first sample, counted from the first sample of the ringbuffer
+    pub sample_index: u64,
+    /// jack frame time of the first sample if the producer reports `StreamTime`,
+    /// wraps around like jack's frame counter
+    pub frame_time: Option<u32>,
+    /// estimated wall clock time of the first sample
+    pub wall_time: SystemTime,
+}
+
+impl Default for Timestamp {
+    fn default() -> Self {
+        Self {
+            sample_index: 0,
+            frame_time: None,
+            wall_time: SystemTime::UNIX_EPOCH,
+        }
+    }
+}
+
+impl Timestamp {
+    /// time of the sample `samples` before this one, not before the first sample
+    pub fn earlier(&self, samples: u64, sample_rate: f32) -> Timestamp {
+        let samples = samples.min(self.sample_index);
+        Timestamp {
+            sample_index: self.sample_index - samples,
+            frame_time: self
+                .frame_time
+                .map(|frame_time| frame_time.wrapping_sub(samples as u32)),
+            wall_time: self.wall_time
+                - Duration::from_secs_f64(samples as f64 / sample_rate as f64),
+        }
+    }
+}
+
+/// time of a sample reported by the producer of a stream,
+/// e.g. of the first sample of a jack period
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StreamTime {
+    /// number of samples pushed into the ringbuffer before this one
+    pub sample_index: u64,
+    /// jack frame time of the sample
+    pub frame_time: u32,
+    pub wall_time: SystemTime,
+}
+
+impl StreamTime {
+    /// time of another sample of the stream, extrapolated with `sample_rate`.
+    /// samples dropped between both make the result off by their duration
+    pub fn timestamp(&self, sample_index: u64, sample_rate: f32) -> Timestamp {
+        let offset = sample_index as i64 - self.sample_index as i64;
+        let duration = Duration::from_secs_f64(offset.unsigned_abs() as f64 / sample_rate as f64);
+        let wall_time = if offset >= 0 {
+            self.wall_time + duration
+        } else {
+            self.wall_time - duration
+        };
+        Timestamp {
+            sample_index,
+            frame_time: Some(self.frame_time.wrapping_add(offset as u32)),
+            wall_time,
+        }
+    }
+}
+
+/// counters of audio lost on the way into the ringbuffers of a `StftHandler`
+/// and the latest `StreamTime` of its producer,
+/// shared as `Arc<StreamStats>` between the jack callbacks and the handler
+#[derive(Default, Debug)]
+pub struct StreamStats {
+    dropped_samples: AtomicUsize,
+    xruns: AtomicUsize,
+    time: Mutex<Option<StreamTime>>,
+}
+
+impl StreamStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// counts samples which did not fit into a full ringbuffer
+    pub fn add_dropped_samples(&self, count: usize) {
+        if count > 0 {
+            self.dropped_samples.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// counts a buffer under or overrun of the jack server
+    pub fn add_xrun(&self) {
+        self.xruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dropped_samples(&self) -> usize {
+        self.dropped_samples.load(Ordering::Relaxed)
+    }
+
+    pub fn xruns(&self) -> usize {
+        self.xruns.load(Ordering::Relaxed)
+    }
+
+    /// reports the time of a sample, does not block:
+    /// skipped while a reader holds the lock, the next report follows a period later
+    pub fn set_time(&self, stream_time: StreamTime) {
+        if let Ok(mut time) = self.time.try_lock() {
+            *time = Some(stream_time);
+        }
+    }
+
+    /// latest reported time, `None` if the producer does not report it
+    pub fn time(&self) -> Option<StreamTime> {
+        *self.time.lock().expect("Unlock")
+    }
+}